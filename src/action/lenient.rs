@@ -0,0 +1,480 @@
+// Normalizes the common ways LLMs drift from the requested action format before parsing
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::de::Error;
+use serde_yaml::{Mapping, Value};
+
+use super::VARIANTS;
+
+/// Keys that may start the action in a reply, used to skip any prose the model wrote before it.
+const KNOWN_KEYS: &[&str] = &[
+    "type",
+    "name",
+    "desc",
+    "description",
+    "title",
+    "item",
+    "message",
+    "response",
+    "action",
+    "actions",
+];
+
+/// Keys the action, or the list of actions, is sometimes nested under.
+const WRAPPER_KEYS: &[&str] = &["response", "action", "actions"];
+
+/// Turns a model reply into a YAML value that should deserialize into an `Action`, or into a list
+/// of them when the reply is a list.
+pub fn normalize(text: &str) -> Result<Value, serde_yaml::Error> {
+    let text = strip_code_fence(text);
+    let text = strip_prose(&text);
+
+    match unwrap_wrappers(serde_yaml::from_str(&text)?) {
+        Value::Sequence(actions) => actions
+            .into_iter()
+            .map(|action| normalize_action(unwrap_wrappers(action)))
+            .collect::<Result<_, _>>()
+            .map(Value::Sequence),
+        action => normalize_action(action),
+    }
+}
+
+fn normalize_action(value: Value) -> Result<Value, serde_yaml::Error> {
+    let Value::Mapping(mapping) = value else {
+        return Err(serde_yaml::Error::custom("response is not a mapping"));
+    };
+
+    Ok(Value::Mapping(normalize_mapping(mapping)))
+}
+
+/// Extracts the narrative text (the message or scene description) from a reply that may still be
+/// streaming in, so it can be shown to the player before the whole action has arrived.
+pub fn partial_narrative(text: &str) -> Option<String> {
+    static NARRATIVE_KEY: OnceLock<Regex> = OnceLock::new();
+    let narrative_key = NARRATIVE_KEY.get_or_init(|| {
+        Regex::new(r#"(?i)["']?\b(message|desc|description)["']?\s*:[ \t]*"#).unwrap()
+    });
+    let start = narrative_key.find_iter(text).last()?.end();
+    let value = text[start..]
+        .trim_start_matches(['|', '>', '-', '+'])
+        .trim_start();
+
+    let narrative = match value.strip_prefix('"') {
+        // A JSON or double-quoted YAML string, which ends at the next unescaped quote.
+        Some(quoted) => {
+            let mut narrative = String::new();
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('n') => narrative.push('\n'),
+                        Some(c) => narrative.push(c),
+                        None => {}
+                    },
+                    '"' => break,
+                    c => narrative.push(c),
+                }
+            }
+            narrative
+        }
+        // A plain or block YAML scalar, which ends at the next key.
+        None => value
+            .lines()
+            .enumerate()
+            .take_while(|(i, line)| *i == 0 || !is_key(line) || line.starts_with(' '))
+            .map(|(_, line)| line.trim())
+            .collect::<Vec<&str>>()
+            .join(" "),
+    };
+
+    let narrative = narrative.trim().to_string();
+    (!narrative.is_empty()).then_some(narrative)
+}
+
+/// Returns the contents of the first ``` fenced block, if there is one.
+fn strip_code_fence(text: &str) -> String {
+    let Some(start) = text.find("```") else {
+        return text.to_string();
+    };
+    let after_fence = &text[start + 3..];
+    // Skip the language tag, if any.
+    let body = match after_fence.find('\n') {
+        Some(newline) => &after_fence[newline + 1..],
+        None => after_fence,
+    };
+    match body.find("```") {
+        Some(end) => body[..end].to_string(),
+        None => body.to_string(),
+    }
+}
+
+/// Drops any lines before the first line that looks like the start of an action or a list of
+/// them, and any lines after the actions end.
+fn strip_prose(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(start) = lines.iter().position(|line| {
+        line.trim_start().starts_with(['{', '['])
+            || is_known_key(line)
+            || is_known_key(list_item(line))
+    }) else {
+        return text.to_string();
+    };
+
+    let json_end = match lines[start].trim_start().chars().next() {
+        Some('{') => Some('}'),
+        Some('[') => Some(']'),
+        _ => None,
+    };
+    if let Some(json_end) = json_end {
+        let json = lines[start..].join("\n");
+        return match json.rfind(json_end) {
+            Some(end) => json[..=end].to_string(),
+            None => json,
+        };
+    }
+
+    let mut action_lines = Vec::new();
+    for line in &lines[start..] {
+        let is_continuation = line.starts_with(' ') || line.starts_with('\t');
+        if !line.trim().is_empty() && !is_continuation && !is_key(line) && !is_key(list_item(line))
+        {
+            break;
+        }
+        action_lines.push(*line);
+    }
+    action_lines.join("\n")
+}
+
+fn is_key(line: &str) -> bool {
+    match line.trim_start().split_once(':') {
+        Some((key, _)) => !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_'),
+        None => false,
+    }
+}
+
+/// The rest of a line that starts a YAML list item, or an empty string for any other line.
+fn list_item(line: &str) -> &str {
+    line.trim_start().strip_prefix("- ").unwrap_or_default()
+}
+
+fn is_known_key(line: &str) -> bool {
+    is_key(line)
+        && line
+            .trim_start()
+            .split_once(':')
+            .is_some_and(|(key, _)| KNOWN_KEYS.contains(&key.to_lowercase().as_str()))
+}
+
+fn unwrap_wrappers(mut value: Value) -> Value {
+    while let Some(inner) = unwrap_wrapper(&value) {
+        value = inner;
+    }
+    value
+}
+
+fn unwrap_wrapper(value: &Value) -> Option<Value> {
+    let Value::Mapping(mapping) = value else {
+        return None;
+    };
+    if mapping.len() != 1 {
+        return None;
+    }
+    let (key, inner) = mapping.iter().next()?;
+    let key = key.as_str()?.to_lowercase();
+    if WRAPPER_KEYS.contains(&key.as_str()) && (inner.is_mapping() || inner.is_sequence()) {
+        return Some(inner.clone());
+    }
+    None
+}
+
+fn normalize_mapping(mapping: Mapping) -> Mapping {
+    let mut normalized = Mapping::new();
+    for (key, value) in mapping {
+        let key = match key.as_str() {
+            Some(key) => Value::String(key.to_lowercase()),
+            None => key,
+        };
+        normalized.insert(key, value);
+    }
+
+    if let Some(Value::String(name)) = normalized.get("type") {
+        let name = canonical_variant(name).unwrap_or(name).to_string();
+        normalized.insert(Value::from("type"), Value::from(name));
+    }
+
+    let Some(spec) = normalized
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(|name| VARIANTS.iter().find(|spec| spec.name == name))
+    else {
+        return normalized;
+    };
+
+    // Fill in any missing fields from the names models most often use instead.
+    for (field, _) in spec.fields {
+        if normalized.contains_key(*field) {
+            continue;
+        }
+        // An alternative that is a field of the variant in its own right is never taken.
+        let alternative = field_alternatives(field).iter().find(|alternative| {
+            normalized.contains_key(**alternative)
+                && !spec.fields.iter().any(|(field, _)| field == *alternative)
+        });
+        if let Some(alternative) = alternative {
+            let value = normalized.remove(*alternative).unwrap();
+            normalized.insert(Value::from(*field), value);
+        }
+    }
+
+    normalized
+}
+
+/// Matches a type name regardless of case, spaces, dashes or underscores.
+fn canonical_variant(name: &str) -> Option<&'static str> {
+    let simplify = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let name = simplify(name);
+    VARIANTS
+        .iter()
+        .find(|spec| simplify(spec.name) == name)
+        .map(|spec| spec.name)
+}
+
+fn field_alternatives(field: &str) -> &'static [&'static str] {
+    match field {
+        "name" => &["title", "scene", "location"],
+        "desc" => &["description", "message", "text"],
+        "item" => &["item_name", "object", "name"],
+        "message" => &["text", "desc", "description"],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::Action;
+
+    fn information(message: &str) -> Action {
+        Action::Information {
+            message: String::from(message),
+        }
+    }
+
+    fn new_scene(name: &str, desc: &str) -> Action {
+        Action::NewScene {
+            name: String::from(name),
+            desc: String::from(desc),
+        }
+    }
+
+    fn add_to_inventory(item: &str, message: &str) -> Action {
+        Action::AddToInventory {
+            item: String::from(item),
+            message: String::from(message),
+            quantity: 1,
+            desc: String::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lenient_parse_corpus() {
+        let corpus = vec![
+            (
+                "well formed",
+                "type: Information\nmessage: You see a door.",
+                information("You see a door."),
+            ),
+            (
+                "response wrapper",
+                "response:\n  type: Information\n  message: You see a door.",
+                information("You see a door."),
+            ),
+            (
+                "yaml code fence",
+                "```yaml\ntype: Information\nmessage: You see a door.\n```",
+                information("You see a door."),
+            ),
+            (
+                "bare code fence with prose around it",
+                "Sure! Here is the action:\n```\ntype: Information\nmessage: You see a door.\n```\nLet me know what you do next.",
+                information("You see a door."),
+            ),
+            (
+                "prose before yaml",
+                "Here is my response:\n\ntype: AddToInventory\nitem: Strange Key\nmessage: You take the key.",
+                add_to_inventory("Strange Key", "You take the key."),
+            ),
+            (
+                "prose after yaml",
+                "type: Information\nmessage: You see a door.\n\nI hope this adds to the mystery!",
+                information("You see a door."),
+            ),
+            (
+                "lowercase type",
+                "type: newscene\nname: In the Woods\ndesc: Tall trees surround you.",
+                new_scene("In the Woods", "Tall trees surround you."),
+            ),
+            (
+                "snake case type",
+                "type: add_to_inventory\nitem: Lamp\nmessage: You take the lamp.",
+                add_to_inventory("Lamp", "You take the lamp."),
+            ),
+            (
+                "spaced type",
+                "type: End Game\nmessage: You escape the simulation.",
+                Action::EndGame {
+                    message: String::from("You escape the simulation."),
+                },
+            ),
+            (
+                "capitalized keys",
+                "Type: Information\nMessage: You see a door.",
+                information("You see a door."),
+            ),
+            (
+                "message instead of desc on NewScene",
+                "type: NewScene\nname: In the Woods\nmessage: Tall trees surround you.",
+                new_scene("In the Woods", "Tall trees surround you."),
+            ),
+            (
+                "description and title on NewScene",
+                "type: NewScene\ntitle: In the Woods\ndescription: Tall trees surround you.",
+                new_scene("In the Woods", "Tall trees surround you."),
+            ),
+            (
+                "json object",
+                r#"{"type": "Information", "message": "You see a door."}"#,
+                information("You see a door."),
+            ),
+            (
+                "structured output wrapper",
+                r#"{"action": {"type": "Information", "message": "You see a door."}}"#,
+                information("You see a door."),
+            ),
+            (
+                "json with prose",
+                "Here you go: \n{\"type\": \"information\", \"message\": \"You see a door.\"}\nEnjoy!",
+                information("You see a door."),
+            ),
+            (
+                "multiline block scalar",
+                "type: NewScene\nname: In the Woods\ndesc: |\n  Tall trees surround you.\n  A path leads north.",
+                new_scene("In the Woods", "Tall trees surround you.\nA path leads north."),
+            ),
+            (
+                "quoted values",
+                "type: \"Information\"\nmessage: \"You see a door: it is locked.\"",
+                information("You see a door: it is locked."),
+            ),
+        ];
+
+        for (name, input, expected) in corpus {
+            let actual = Action::parse(input);
+            assert_eq!(actual.ok(), Some(expected), "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn lenient_parse_lists() {
+        let take_and_go = vec![
+            add_to_inventory("Lamp", "You take the lamp."),
+            new_scene("The Yard", "Weeds everywhere."),
+        ];
+        let corpus = vec![
+            (
+                "yaml list",
+                "- type: AddToInventory\n  item: Lamp\n  message: You take the lamp.\n- type: NewScene\n  name: The Yard\n  desc: Weeds everywhere.",
+                take_and_go.clone(),
+            ),
+            (
+                "list with prose and lenient fields",
+                "Here are the actions:\n\n- type: add_to_inventory\n  item: Lamp\n  message: You take the lamp.\n- Type: NewScene\n  title: The Yard\n  description: Weeds everywhere.\n\nHave fun!",
+                take_and_go.clone(),
+            ),
+            (
+                "actions wrapper",
+                "actions:\n  - type: AddToInventory\n    item: Lamp\n    message: You take the lamp.\n  - type: NewScene\n    name: The Yard\n    desc: Weeds everywhere.",
+                take_and_go.clone(),
+            ),
+            (
+                "json array in a code fence",
+                "```json\n[{\"type\": \"AddToInventory\", \"item\": \"Lamp\", \"message\": \"You take the lamp.\"}, {\"type\": \"NewScene\", \"name\": \"The Yard\", \"desc\": \"Weeds everywhere.\"}]\n```",
+                take_and_go,
+            ),
+            (
+                "single action",
+                "Sure:\ntype: information\nmessage: You see a door.",
+                vec![information("You see a door.")],
+            ),
+        ];
+
+        for (name, input, expected) in corpus {
+            let actual = Action::parse_all(input);
+            assert_eq!(actual.ok(), Some(expected), "case: {}", name);
+        }
+
+        assert!(Action::parse_all("- type: Dance\n  message: You dance.").is_err());
+    }
+
+    #[test]
+    fn lenient_partial_narrative() {
+        let corpus = vec![
+            ("type only", "type: Information\n", None),
+            ("empty message", "type: Information\nmessage: ", None),
+            (
+                "partial message",
+                "type: Information\nmessage: You see a do",
+                Some("You see a do"),
+            ),
+            (
+                "scene description before other keys",
+                "type: NewScene\ndesc: Tall trees\nname: In the",
+                Some("Tall trees"),
+            ),
+            (
+                "block scalar",
+                "type: NewScene\ndesc: |\n  Tall trees.\n  A path",
+                Some("Tall trees. A path"),
+            ),
+            (
+                "partial json",
+                r#"{"action": {"type": "Information", "message": "You see a \"door\"."#,
+                Some("You see a \"door\"."),
+            ),
+            (
+                "complete json",
+                r#"{"type": "Information", "message": "Hello.\nGoodbye."}"#,
+                Some("Hello.\nGoodbye."),
+            ),
+        ];
+
+        for (name, input, expected) in corpus {
+            let actual = super::partial_narrative(input);
+            assert_eq!(actual.as_deref(), expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn lenient_parse_rejects_garbage() {
+        let corpus = vec![
+            ("empty", ""),
+            ("prose only", "I'm not sure what you mean."),
+            ("unknown type", "type: Dance\nmessage: You dance."),
+            (
+                "missing field",
+                "type: AddToInventory\nmessage: You take it.",
+            ),
+        ];
+
+        for (name, input) in corpus {
+            assert!(Action::parse(input).is_err(), "case: {}", name);
+        }
+    }
+}
@@ -1,19 +1,385 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::model::stats::Stat;
+
+pub use self::lenient::partial_narrative;
+
+mod lenient;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum Action {
-    NewScene { name: String, desc: String },
-    AddToInventory { item: String, message: String },
-    RemoveFromInventory { item: String, message: String },
-    Information { message: String },
-    EndGame { message: String },
+    NewScene {
+        name: String,
+        desc: String,
+    },
+    /// Adding an item the player already has adds to its quantity.
+    AddToInventory {
+        item: String,
+        message: String,
+        #[serde(default = "one", skip_serializing_if = "is_one")]
+        quantity: u32,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        desc: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+    },
+    RemoveFromInventory {
+        item: String,
+        message: String,
+    },
+    /// Changes how many of an item the player has, by a negative `change` for items that are
+    /// used up. The item is gone once none are left.
+    ChangeQuantity {
+        item: String,
+        change: i32,
+        message: String,
+    },
+    AddToScene {
+        item: String,
+        message: String,
+    },
+    /// Changes the player's health, stamina or gold. The game ends when health reaches zero.
+    ModifyStat {
+        stat: Stat,
+        change: i32,
+        message: String,
+    },
+    Information {
+        message: String,
+    },
+    EndGame {
+        message: String,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn is_one(quantity: &u32) -> bool {
+    *quantity == 1
 }
 
+/// The wrapper object a structured output reply is expected to have, since schemas for structured
+/// output must have an object at the root.
+#[derive(Deserialize)]
+struct StructuredReply {
+    actions: Vec<Action>,
+}
+
+struct VariantSpec {
+    name: &'static str,
+    description: &'static str,
+    fields: &'static [(&'static str, &'static str)],
+}
+
+/// Describes each variant of `Action` and its fields. Must be kept in sync with the enum.
+const VARIANTS: &[VariantSpec] = &[
+    VariantSpec {
+        name: "NewScene",
+        description: "Move the player to a new location.",
+        fields: &[
+            ("name", "The name of the new location."),
+            ("desc", "A description of the new location."),
+        ],
+    },
+    VariantSpec {
+        name: "AddToInventory",
+        description: "Add an item to the player's inventory.",
+        fields: &[
+            ("item", "The name of the item."),
+            (
+                "message",
+                "A message describing how the player got the item.",
+            ),
+            (
+                "quantity",
+                "How many of the item the player gets, usually 1.",
+            ),
+            (
+                "desc",
+                "A short description of the item, shown when the player examines it.",
+            ),
+            (
+                "tags",
+                "Short tags for the kind of item, such as weapon, key or food.",
+            ),
+        ],
+    },
+    VariantSpec {
+        name: "RemoveFromInventory",
+        description: "Remove an item from the player's inventory.",
+        fields: &[
+            (
+                "item",
+                "The name of the item, exactly as it appears in the inventory.",
+            ),
+            (
+                "message",
+                "A message describing how the player lost the item.",
+            ),
+        ],
+    },
+    VariantSpec {
+        name: "ChangeQuantity",
+        description:
+            "Change how many of an item the player has, such as coins spent or arrows shot.",
+        fields: &[
+            (
+                "item",
+                "The name of the item, exactly as it appears in the inventory.",
+            ),
+            (
+                "change",
+                "How much the quantity changes by, negative when the player uses up or loses some.",
+            ),
+            ("message", "A message describing the change."),
+        ],
+    },
+    VariantSpec {
+        name: "AddToScene",
+        description: "Place an item in the current location, such as one the player uncovers.",
+        fields: &[
+            ("item", "The name of the item."),
+            ("message", "A message describing how the item appeared."),
+        ],
+    },
+    VariantSpec {
+        name: "ModifyStat",
+        description: "Change the player's health, stamina or gold, such as when they are hurt, rest or are paid.",
+        fields: &[
+            ("stat", "The stat that changes: health, stamina or gold."),
+            (
+                "change",
+                "How much the stat changes by, negative when the player loses some.",
+            ),
+            ("message", "A message describing the change."),
+        ],
+    },
+    VariantSpec {
+        name: "Information",
+        description: "Tell the player something without changing the game state.",
+        fields: &[("message", "The message to show the player.")],
+    },
+    VariantSpec {
+        name: "EndGame",
+        description: "End the game, whether the player won or lost.",
+        fields: &[("message", "A message describing how the game ended.")],
+    },
+];
+
 impl Action {
     pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
         serde_yaml::from_str(yaml)
     }
+
+    /// Parses a model reply, tolerating the common ways models drift from the requested format:
+    /// code fences, prose around the action, wrapper keys, the wrong case of type names and
+    /// misnamed fields.
+    pub fn parse(text: &str) -> Result<Self, serde_yaml::Error> {
+        Action::from_yaml(text).or_else(|_| serde_yaml::from_value(lenient::normalize(text)?))
+    }
+
+    /// Parses a model reply holding the actions of a turn, which is a list of actions to apply in
+    /// order, or a single action. Tolerates the same drift as `Action::parse`.
+    pub fn parse_all(text: &str) -> Result<Vec<Self>, serde_yaml::Error> {
+        if let Ok(actions) = one_or_many::deserialize(serde_yaml::Deserializer::from_str(text)) {
+            return Ok(actions);
+        }
+
+        match lenient::normalize(text)? {
+            serde_yaml::Value::Sequence(actions) => {
+                actions.into_iter().map(serde_yaml::from_value).collect()
+            }
+            _ => Action::parse(text).map(|action| vec![action]),
+        }
+    }
+
+    /// Parses a reply that was generated against `Action::json_schema`.
+    pub fn from_structured_json(json: &str) -> Result<Vec<Self>, serde_json::Error> {
+        serde_json::from_str::<StructuredReply>(json).map(|reply| reply.actions)
+    }
+
+    /// Returns a copy of the action with its narrative text cut down to at most `max_chars`
+    /// characters, for fitting older actions into a limited context.
+    pub fn shortened(&self, max_chars: usize) -> Action {
+        let shorten = |text: &String| shorten(text, max_chars);
+
+        match self {
+            Action::NewScene { name, desc } => Action::NewScene {
+                name: name.clone(),
+                desc: shorten(desc),
+            },
+            Action::AddToInventory {
+                item,
+                message,
+                quantity,
+                desc,
+                tags,
+            } => Action::AddToInventory {
+                item: item.clone(),
+                message: shorten(message),
+                quantity: *quantity,
+                desc: shorten(desc),
+                tags: tags.clone(),
+            },
+            Action::RemoveFromInventory { item, message } => Action::RemoveFromInventory {
+                item: item.clone(),
+                message: shorten(message),
+            },
+            Action::ChangeQuantity {
+                item,
+                change,
+                message,
+            } => Action::ChangeQuantity {
+                item: item.clone(),
+                change: *change,
+                message: shorten(message),
+            },
+            Action::AddToScene { item, message } => Action::AddToScene {
+                item: item.clone(),
+                message: shorten(message),
+            },
+            Action::ModifyStat {
+                stat,
+                change,
+                message,
+            } => Action::ModifyStat {
+                stat: *stat,
+                change: *change,
+                message: shorten(message),
+            },
+            Action::Information { message } => Action::Information {
+                message: shorten(message),
+            },
+            Action::EndGame { message } => Action::EndGame {
+                message: shorten(message),
+            },
+        }
+    }
+
+    /// Parses a call to one of the tools from `Action::tool_definitions`, given the name of the
+    /// tool and its JSON arguments.
+    pub fn from_tool_call(name: &str, arguments: &str) -> Result<Self, serde_json::Error> {
+        let mut arguments: Map<String, Value> = serde_json::from_str(arguments)?;
+        arguments.insert(String::from("type"), json!(name));
+        serde_json::from_value(Value::Object(arguments))
+    }
+
+    /// A JSON schema for a structured output reply containing the actions of a turn, in the order
+    /// they should be applied. The schema is compatible with the strict mode of OpenAI's
+    /// `response_format`.
+    pub fn json_schema() -> Value {
+        let variants: Vec<Value> = VARIANTS
+            .iter()
+            .map(|variant| {
+                let mut schema = variant_schema(variant);
+                schema["description"] = json!(variant.description);
+                schema["properties"]["type"] = json!({ "type": "string", "enum": [variant.name] });
+                schema["required"]
+                    .as_array_mut()
+                    .unwrap()
+                    .insert(0, json!("type"));
+                schema
+            })
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": {
+                "actions": {
+                    "type": "array",
+                    "items": { "anyOf": variants }
+                }
+            },
+            "required": ["actions"],
+            "additionalProperties": false
+        })
+    }
+
+    /// One tool per variant, in the format of OpenAI's `tools`. The name of each tool is the
+    /// variant's type, and its parameters are the variant's fields.
+    pub fn tool_definitions() -> Value {
+        VARIANTS
+            .iter()
+            .map(|variant| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": variant.name,
+                        "description": variant.description,
+                        "parameters": variant_schema(variant)
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Cuts text down to at most `max_chars` characters, marking the cut with an ellipsis.
+pub fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return String::from(text);
+    }
+    let shortened: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", shortened.trim_end())
+}
+
+/// A schema for an object with the fields of the variant, all of them required. Fields are
+/// strings, apart from the quantities and tags of items and the names of stats.
+fn variant_schema(variant: &VariantSpec) -> Value {
+    let mut properties = Map::new();
+    for (field, description) in variant.fields {
+        let mut schema = match *field {
+            "quantity" | "change" => json!({ "type": "integer" }),
+            "tags" => json!({ "type": "array", "items": { "type": "string" } }),
+            "stat" => json!({ "type": "string", "enum": Stat::NAMES }),
+            _ => json!({ "type": "string" }),
+        };
+        schema["description"] = json!(description);
+        properties.insert(String::from(*field), schema);
+    }
+    let required: Vec<&str> = variant.fields.iter().map(|(field, _)| *field).collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false
+    })
+}
+
+/// Serializes the actions of a turn as a single action when there is only one, the way turns were
+/// saved before they could have several, and deserializes either form.
+pub mod one_or_many {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Action;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Action),
+        Many(Vec<Action>),
+    }
+
+    pub fn serialize<S: Serializer>(actions: &[Action], serializer: S) -> Result<S::Ok, S::Error> {
+        match actions {
+            [action] => action.serialize(serializer),
+            actions => actions.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Action>, D::Error> {
+        Ok(match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(action) => vec![action],
+            OneOrMany::Many(actions) => actions,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -44,11 +410,17 @@ mod test {
             type: AddToInventory
             item: Test Item
             message: You picked up a test item.
+            quantity: 3
+            desc: A small test item.
+            tags: [test]
         "#;
 
         let expected = Action::AddToInventory {
             item: String::from("Test Item"),
             message: String::from("You picked up a test item."),
+            quantity: 3,
+            desc: String::from("A small test item."),
+            tags: vec![String::from("test")],
         };
 
         let actual: Action = Action::from_yaml(yaml).unwrap();
@@ -74,6 +446,64 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn action_deserialize_change_quantity() {
+        let yaml = r#"
+            type: ChangeQuantity
+            item: Gold Coin
+            change: -2
+            message: You pay the ferryman two coins.
+        "#;
+
+        let expected = Action::ChangeQuantity {
+            item: String::from("Gold Coin"),
+            change: -2,
+            message: String::from("You pay the ferryman two coins."),
+        };
+
+        let actual: Action = Action::from_yaml(yaml).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn action_deserialize_add_to_scene() {
+        let yaml = r#"
+            type: AddToScene
+            item: Test Item
+            message: A test item falls from the ceiling.
+        "#;
+
+        let expected = Action::AddToScene {
+            item: String::from("Test Item"),
+            message: String::from("A test item falls from the ceiling."),
+        };
+
+        let actual: Action = Action::from_yaml(yaml).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn action_deserialize_modify_stat() {
+        let yaml = r#"
+            type: ModifyStat
+            stat: health
+            change: -10
+            message: The wolf bites your arm.
+        "#;
+
+        let expected = Action::ModifyStat {
+            stat: Stat::Health,
+            change: -10,
+            message: String::from("The wolf bites your arm."),
+        };
+
+        let actual: Action = Action::from_yaml(yaml).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn action_deserialize_information() {
         let yaml = r#"
@@ -105,4 +535,113 @@ mod test {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn action_deserialize_structured_json() {
+        let json = r#"{"actions": [
+            {"type": "AddToInventory", "item": "Lamp", "message": "You take the lamp."},
+            {"type": "Information", "message": "Hello."}
+        ]}"#;
+
+        let expected = vec![
+            Action::AddToInventory {
+                item: String::from("Lamp"),
+                message: String::from("You take the lamp."),
+                quantity: 1,
+                desc: String::new(),
+                tags: Vec::new(),
+            },
+            Action::Information {
+                message: String::from("Hello."),
+            },
+        ];
+
+        let actual = Action::from_structured_json(json).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn action_from_tool_call() {
+        let expected = Action::AddToInventory {
+            item: String::from("Brass Lamp"),
+            message: String::from("You take the lamp."),
+            quantity: 1,
+            desc: String::new(),
+            tags: Vec::new(),
+        };
+
+        let actual = Action::from_tool_call(
+            "AddToInventory",
+            r#"{"item": "Brass Lamp", "message": "You take the lamp."}"#,
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(Action::from_tool_call("Dance", r#"{"message": "a"}"#).is_err());
+        assert!(Action::from_tool_call("Information", "not json").is_err());
+    }
+
+    #[test]
+    fn action_tool_definitions_match_variants() {
+        let tools = Action::tool_definitions();
+        let names: Vec<&str> = tools
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["function"]["name"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "NewScene",
+                "AddToInventory",
+                "RemoveFromInventory",
+                "ChangeQuantity",
+                "AddToScene",
+                "ModifyStat",
+                "Information",
+                "EndGame"
+            ]
+        );
+        assert_eq!(
+            tools[0]["function"]["parameters"]["required"],
+            json!(["name", "desc"])
+        );
+    }
+
+    #[test]
+    fn action_json_schema_matches_variants() {
+        let examples = vec![
+            Action::from_yaml("{type: NewScene, name: a, desc: b}").unwrap(),
+            Action::from_yaml(
+                "{type: AddToInventory, item: a, message: b, quantity: 2, desc: c, tags: [d]}",
+            )
+            .unwrap(),
+            Action::from_yaml("{type: RemoveFromInventory, item: a, message: b}").unwrap(),
+            Action::from_yaml("{type: ChangeQuantity, item: a, change: -1, message: b}").unwrap(),
+            Action::from_yaml("{type: AddToScene, item: a, message: b}").unwrap(),
+            Action::from_yaml("{type: ModifyStat, stat: gold, change: 5, message: a}").unwrap(),
+            Action::from_yaml("{type: Information, message: a}").unwrap(),
+            Action::from_yaml("{type: EndGame, message: a}").unwrap(),
+        ];
+        let schema = Action::json_schema();
+        let variants = schema["properties"]["actions"]["items"]["anyOf"]
+            .as_array()
+            .unwrap();
+
+        assert_eq!(variants.len(), examples.len());
+        for (variant, example) in variants.iter().zip(examples) {
+            let example = serde_json::to_value(example).unwrap();
+            let mut expected: Vec<&String> = example.as_object().unwrap().keys().collect();
+            let mut actual: Vec<&String> =
+                variant["properties"].as_object().unwrap().keys().collect();
+            expected.sort();
+            actual.sort();
+
+            assert_eq!(actual, expected);
+            assert_eq!(variant["properties"]["type"]["enum"][0], example["type"]);
+        }
+    }
 }
@@ -0,0 +1,199 @@
+// The config is loaded once at startup and describes how the game should talk to its LLM backend
+
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::game_loader::{
+    backend::{
+        AnthropicBackend, AnthropicConfig, Backend, HttpConfig, MockBackend, MockConfig,
+        OpenAiBackend, OpenAiConfig, TemplateBackend, TemplateConfig,
+    },
+    context::ContextConfig,
+    prompts::PromptConfig,
+    usage::PriceTable,
+};
+
+const CONFIG_PATH: &str = "config.yaml";
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    backend: BackendConfig,
+    /// Backends tried in order when the main backend is unreachable or cannot answer.
+    fallbacks: Vec<BackendConfig>,
+    /// Timeouts and retries for backends that make HTTP requests.
+    http: HttpConfig,
+    /// Token budgets for the context sent each turn.
+    context: ContextConfig,
+    /// Prompt template overrides and the prompt packs to pick from at New Game.
+    prompts: PromptConfig,
+    /// Prices per model in dollars per million tokens, for estimating what a session costs.
+    prices: PriceTable,
+    /// Where the game is saved after every turn, and continued from.
+    save_path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: BackendConfig::default(),
+            fallbacks: Vec::new(),
+            http: HttpConfig::default(),
+            context: ContextConfig::default(),
+            prompts: PromptConfig::default(),
+            prices: PriceTable::new(),
+            save_path: String::from("save.yaml"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackendConfig {
+    OpenAi(Box<OpenAiConfig>),
+    Anthropic(Box<AnthropicConfig>),
+    Template(Box<TemplateConfig>),
+    Mock(MockConfig),
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig::OpenAi(Box::default())
+    }
+}
+
+impl BackendConfig {
+    pub fn build(&self, http: &HttpConfig) -> Box<dyn Backend> {
+        match self {
+            BackendConfig::OpenAi(config) => {
+                Box::new(OpenAiBackend::new((**config).clone(), http.clone()))
+            }
+            BackendConfig::Anthropic(config) => {
+                Box::new(AnthropicBackend::new((**config).clone(), http.clone()))
+            }
+            BackendConfig::Template(config) => {
+                Box::new(TemplateBackend::new((**config).clone(), http.clone()))
+            }
+            BackendConfig::Mock(config) => Box::new(MockBackend::new(config.clone())),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from the path in `ADVENTUI_CONFIG`, or `config.yaml` in the working
+    /// directory. A missing file is not an error - the defaults target the OpenAI API.
+    pub fn load() -> Self {
+        let path = std::env::var("ADVENTUI_CONFIG").unwrap_or(String::from(CONFIG_PATH));
+        match fs::read_to_string(&path) {
+            Ok(yaml) => Config::from_yaml(&yaml).expect("Failed to parse config file."),
+            Err(_) => Config::default(),
+        }
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    pub fn get_backend(&self) -> &BackendConfig {
+        &self.backend
+    }
+
+    pub fn get_fallbacks(&self) -> &[BackendConfig] {
+        &self.fallbacks
+    }
+
+    pub fn get_prompts(&self) -> &PromptConfig {
+        &self.prompts
+    }
+
+    pub fn get_prices(&self) -> &PriceTable {
+        &self.prices
+    }
+
+    pub fn get_http(&self) -> &HttpConfig {
+        &self.http
+    }
+
+    pub fn get_context(&self) -> &ContextConfig {
+        &self.context
+    }
+
+    pub fn get_save_path(&self) -> &str {
+        &self.save_path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_defaults_to_openai() {
+        let config = Config::from_yaml("{}").unwrap();
+
+        assert_eq!(config.get_backend(), &BackendConfig::OpenAi(Box::default()));
+    }
+
+    #[test]
+    fn config_deserialize_openai_backend() {
+        let yaml = r#"
+            backend:
+              type: openai
+              base_url: http://localhost:8080/v1
+        "#;
+
+        let expected: OpenAiConfig =
+            serde_yaml::from_str("base_url: http://localhost:8080/v1").unwrap();
+
+        let config = Config::from_yaml(yaml).unwrap();
+
+        assert_eq!(
+            config.get_backend(),
+            &BackendConfig::OpenAi(Box::new(expected))
+        );
+    }
+
+    #[test]
+    fn config_deserialize_anthropic_backend() {
+        let yaml = r#"
+            backend:
+              type: anthropic
+              stream: true
+        "#;
+
+        let expected: AnthropicConfig = serde_yaml::from_str("stream: true").unwrap();
+
+        let config = Config::from_yaml(yaml).unwrap();
+
+        assert_eq!(
+            config.get_backend(),
+            &BackendConfig::Anthropic(Box::new(expected))
+        );
+    }
+
+    #[test]
+    fn config_deserialize_fallbacks() {
+        let yaml = r#"
+            backend:
+              type: openai
+            fallbacks:
+              - type: anthropic
+              - type: openai
+                base_url: http://localhost:11434/v1
+        "#;
+
+        let config = Config::from_yaml(yaml).unwrap();
+
+        assert_eq!(
+            config.get_fallbacks(),
+            &[
+                BackendConfig::Anthropic(Box::default()),
+                BackendConfig::OpenAi(Box::new(
+                    serde_yaml::from_str("base_url: http://localhost:11434/v1").unwrap()
+                )),
+            ]
+        );
+        assert!(Config::from_yaml("{}").unwrap().get_fallbacks().is_empty());
+    }
+}
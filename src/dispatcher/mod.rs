@@ -1,45 +1,90 @@
 use crossterm::event::{self, KeyCode, KeyEventKind};
-use std::{sync::mpsc::Sender, thread::JoinHandle};
+use std::{sync::mpsc::Sender, thread::JoinHandle, time::Duration};
 
 use crate::{
-    action::Action,
-    game_loader::GameLoader,
-    message::Message,
-    model::{game_state::GameState, Model},
+    action::{self, Action},
+    game_loader::{
+        backend::{Cancellation, Progress},
+        prompts::DEFAULT_PACK,
+        GameLoader,
+    },
+    message::{Message, TurnEvent, TurnRequest},
+    model::{
+        game_state::GameState,
+        item::Item,
+        main_menu_state::{MainMenuState, Menu},
+        Model,
+    },
+    worker::Worker,
 };
 
+/// How long to wait for a keypress before checking on the worker and advancing the spinner.
+const TICK_RATE: Duration = Duration::from_millis(100);
+
 pub struct Dispatcher {
     join_handle: Option<JoinHandle<()>>,
 }
 
+/// A turn that has been sent to the worker and not yet completed or cancelled.
+struct PendingTurn {
+    id: u64,
+    /// The state from before the turn was submitted, restored if the turn is cancelled. Starting
+    /// or continuing a game has no state to return to, so cancelling it returns to the main menu.
+    snapshot: Option<GameState>,
+    /// Set when the turn is cancelled, so the worker stops working on it.
+    cancellation: Cancellation,
+}
+
 impl Dispatcher {
     pub fn new(
         app_state_tx: Sender<Message>,
         ui_state_tx: Sender<Message>,
-        mut loader: GameLoader,
+        loader: GameLoader,
     ) -> Self {
+        let packs = loader.get_pack_names();
         let join_handle = std::thread::spawn(move || {
+            let worker = Worker::new(loader);
             let mut model = Model::new();
-            let mut is_new_game = false;
+            let mut pending: Option<PendingTurn> = None;
+            let mut next_turn_id: u64 = 0;
             ui_state_tx
-                .send(Message::StateUpdate(model.clone()))
+                .send(Message::StateUpdate(Box::new(model.clone())))
                 .expect("Failed to initialize application state for UI.");
             loop {
-                if is_new_game {
-                    is_new_game = false;
+                let mut updated = false;
+                while let Some(event) = worker.try_recv() {
+                    let pending_id = pending.as_ref().map(|turn| turn.id);
+                    match (&mut model, event) {
+                        (_, TurnEvent::Resumed { id, state }) if Some(id) == pending_id => {
+                            pending = None;
+                            model = match state {
+                                Some(state) => Model::Game(state),
+                                None => Model::MainMenu(MainMenuState::new()),
+                            };
+                            updated = true;
+                        }
+                        (Model::Game(state), event) => {
+                            updated |= handle_turn_event(&worker, state, &mut pending, event);
+                        }
+                        _ => {}
+                    }
+                }
+
+                if !event::poll(TICK_RATE).expect("Failed to poll for events.") {
                     if let Model::Game(state) = &mut model {
-                        loader.create_game();
-                        let action = loader.process_input("start game", state);
-                        if let Action::NewScene { name, desc } = action {
-                            state.new_scene(name, desc);
-                            state.enable_entry();
+                        if state.is_thinking() {
+                            state.tick_spinner();
+                            updated = true;
                         }
                     }
-                    ui_state_tx
-                        .send(Message::StateUpdate(model.clone()))
-                        .expect("Failed to send updated state to UI.");
+                    if updated {
+                        ui_state_tx
+                            .send(Message::StateUpdate(Box::new(model.clone())))
+                            .expect("Failed to send updated state to UI.");
+                    }
                     continue;
                 }
+
                 if let event::Event::Key(key) =
                     event::read().expect("Failed to read event keypress.")
                 {
@@ -53,15 +98,53 @@ impl Dispatcher {
                                     KeyCode::Char('k') | KeyCode::Up => {
                                         state.select_prev();
                                     }
+                                    KeyCode::Enter if state.get_menu() == Menu::Packs => {
+                                        let pack = String::from(state.get_selection());
+                                        let mut state = GameState::new();
+                                        next_turn_id += 1;
+                                        pending = Some(start_game(
+                                            &worker,
+                                            &mut state,
+                                            next_turn_id,
+                                            &pack,
+                                        ));
+                                        model = Model::Game(Box::new(state));
+                                    }
                                     KeyCode::Enter => match state.get_selection() {
+                                        // The pack is only asked for when there is a choice.
+                                        "New Game" if packs.len() > 1 => {
+                                            model = Model::MainMenu(MainMenuState::packs(
+                                                packs.clone(),
+                                            ));
+                                        }
                                         "New Game" => {
-                                            model = Model::Game(GameState::new());
-                                            is_new_game = true;
+                                            let mut state = GameState::new();
+                                            next_turn_id += 1;
+                                            pending = Some(start_game(
+                                                &worker,
+                                                &mut state,
+                                                next_turn_id,
+                                                DEFAULT_PACK,
+                                            ));
+                                            model = Model::Game(Box::new(state));
+                                        }
+                                        "Continue" => {
+                                            let mut state = GameState::new();
+                                            next_turn_id += 1;
+                                            pending = Some(resume_game(
+                                                &worker,
+                                                &mut state,
+                                                next_turn_id,
+                                            ));
+                                            model = Model::Game(Box::new(state));
                                         }
                                         _ => {
                                             unimplemented!("Main menu option not implemented yet.")
                                         }
                                     },
+                                    KeyCode::Esc if state.get_menu() == Menu::Packs => {
+                                        model = Model::MainMenu(MainMenuState::new());
+                                    }
                                     KeyCode::Esc => {
                                         ui_state_tx
                                             .send(Message::Terminate)
@@ -78,40 +161,25 @@ impl Dispatcher {
                         Model::Game(state) => {
                             if key.kind == KeyEventKind::Press {
                                 match key.code {
-                                    KeyCode::Char(c) => {
+                                    // Once the game is over, the player can only scroll or quit.
+                                    KeyCode::Char(c)
+                                        if state.is_entry_enabled() && !state.is_thinking() =>
+                                    {
                                         state.append_entry(c);
                                     }
-                                    KeyCode::Backspace => {
+                                    KeyCode::Backspace
+                                        if state.is_entry_enabled() && !state.is_thinking() =>
+                                    {
                                         state.remove_last_entry();
                                     }
-                                    KeyCode::Enter => {
-                                        state.disable_entry();
-                                        let action: Action =
-                                            loader.process_input(&state.get_user_entry(), &state);
-                                        state.push_input_to_history();
-                                        match action {
-                                            Action::NewScene { name, desc } => {
-                                                state.new_scene(name, desc);
-                                                state.enable_entry();
-                                            }
-                                            Action::AddToInventory { item, message } => {
-                                                state.add_to_inventory(item);
-                                                state.append_scene_history(message);
-                                                state.enable_entry();
-                                            }
-                                            Action::RemoveFromInventory { item, message } => {
-                                                state.remove_from_inventory(item);
-                                                state.append_scene_history(message);
-                                                state.enable_entry();
-                                            }
-                                            Action::Information { message } => {
-                                                state.append_scene_history(message);
-                                                state.enable_entry();
-                                            }
-                                            Action::EndGame { message } => {
-                                                state.append_scene_history(message);
-                                                state.disable_entry();
-                                            }
+                                    KeyCode::Enter
+                                        if state.is_entry_enabled() && !state.is_thinking() =>
+                                    {
+                                        // Examining a held item is answered without a turn.
+                                        let examined = examine_item(state);
+                                        if !examined {
+                                            next_turn_id += 1;
+                                            pending = submit_entry(&worker, state, next_turn_id);
                                         }
                                     }
                                     KeyCode::Up => {
@@ -127,13 +195,23 @@ impl Dispatcher {
                                         state.scroll_down(10);
                                     }
                                     KeyCode::Esc => {
-                                        ui_state_tx
-                                            .send(Message::Terminate)
-                                            .expect("Failed to send UI terminate message.");
-                                        app_state_tx.send(Message::Terminate).expect(
-                                            "Failed to send application terminate message.",
-                                        );
-                                        break;
+                                        if let Some(turn) = pending.take() {
+                                            // Cancelling drops the turn - the worker stops it
+                                            // early, and it is never committed.
+                                            turn.cancellation.cancel();
+                                            model = match turn.snapshot {
+                                                Some(snapshot) => Model::Game(Box::new(snapshot)),
+                                                None => Model::MainMenu(MainMenuState::new()),
+                                            };
+                                        } else {
+                                            ui_state_tx
+                                                .send(Message::Terminate)
+                                                .expect("Failed to send UI terminate message.");
+                                            app_state_tx.send(Message::Terminate).expect(
+                                                "Failed to send application terminate message.",
+                                            );
+                                            break;
+                                        }
                                     }
                                     _ => {}
                                 }
@@ -142,7 +220,7 @@ impl Dispatcher {
                     }
 
                     ui_state_tx
-                        .send(Message::StateUpdate(model.clone()))
+                        .send(Message::StateUpdate(Box::new(model.clone())))
                         .expect("Failed to send updated state to UI.");
                 }
             }
@@ -162,3 +240,547 @@ impl Dispatcher {
         Ok(())
     }
 }
+
+fn start_game(worker: &Worker, state: &mut GameState, id: u64, pack: &str) -> PendingTurn {
+    state.start_thinking();
+    let cancellation = Cancellation::default();
+    worker.send(TurnRequest::StartGame {
+        id,
+        pack: String::from(pack),
+        cancellation: cancellation.clone(),
+    });
+    PendingTurn {
+        id,
+        snapshot: None,
+        cancellation,
+    }
+}
+
+/// Asks the worker for the saved game. If there is none, the dispatcher returns to the main menu.
+fn resume_game(worker: &Worker, state: &mut GameState, id: u64) -> PendingTurn {
+    state.start_thinking();
+    let cancellation = Cancellation::default();
+    worker.send(TurnRequest::Resume {
+        id,
+        cancellation: cancellation.clone(),
+    });
+    PendingTurn {
+        id,
+        snapshot: None,
+        cancellation,
+    }
+}
+
+/// Answers an entry of "examine <item>" from the stored description of an item the player holds,
+/// without playing a turn. Returns whether the entry was answered. Anything else, including items
+/// without a description, is left to the model.
+fn examine_item(state: &mut GameState) -> bool {
+    let entry = state.get_user_entry().trim().to_lowercase();
+    let Some(name) = entry.strip_prefix("examine ") else {
+        return false;
+    };
+    let name = name.trim_start_matches("the ").trim();
+    let desc = match state.find_in_inventory(name) {
+        Some(item) if !item.get_desc().is_empty() => String::from(item.get_desc()),
+        _ => return false,
+    };
+
+    state.push_input_to_history();
+    state.append_scene_history(desc);
+    true
+}
+
+/// Sends the user's entry to the worker as a new turn. Entries are refused while entry is
+/// disabled, such as once the game is over.
+fn submit_entry(worker: &Worker, state: &mut GameState, id: u64) -> Option<PendingTurn> {
+    if !state.is_entry_enabled() {
+        return None;
+    }
+    let snapshot = state.clone();
+    let input = String::from(state.get_user_entry());
+    state.disable_entry();
+    state.push_input_to_history();
+    state.start_thinking();
+    let cancellation = Cancellation::default();
+    worker.send(TurnRequest::Input {
+        id,
+        input,
+        state: Box::new(snapshot.clone()),
+        cancellation: cancellation.clone(),
+    });
+    Some(PendingTurn {
+        id,
+        snapshot: Some(snapshot),
+        cancellation,
+    })
+}
+
+/// Applies an event from the worker to the state, committing the turn once it is done. Events
+/// from turns that are no longer pending were cancelled, and are ignored. Returns whether the
+/// state changed.
+fn handle_turn_event(
+    worker: &Worker,
+    state: &mut GameState,
+    pending: &mut Option<PendingTurn>,
+    event: TurnEvent,
+) -> bool {
+    let pending_id = pending.as_ref().map(|turn| turn.id);
+    match event {
+        TurnEvent::Progress { id, progress } if Some(id) == pending_id => match progress {
+            Progress::Partial(text) => match action::partial_narrative(&text) {
+                Some(narrative) => {
+                    state.set_pending_response(narrative);
+                    true
+                }
+                None => false,
+            },
+            Progress::Retrying { .. } | Progress::Fallback { .. } => {
+                state.set_thinking_status(progress.to_string());
+                true
+            }
+            // The loader keeps count of usage, which arrives with the finished turn.
+            Progress::Usage(_) => false,
+        },
+        TurnEvent::Done { id, actions, usage } if Some(id) == pending_id => {
+            state.stop_thinking();
+            state.set_usage_status(usage.to_string());
+            apply_actions(state, actions);
+            worker.send(TurnRequest::Commit {
+                state: Box::new(state.clone()),
+            });
+            *pending = None;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Applies the actions of a turn in order, as a single update. The messages of actions before a
+/// new scene are carried into it, so every message of the turn is shown, and the game ends if any
+/// of the actions ends it.
+fn apply_actions(state: &mut GameState, actions: Vec<Action>) {
+    let mut messages = Vec::new();
+    let mut ended = false;
+    let was_alive = !state.get_stats().is_dead();
+    for action in actions {
+        let message = match action {
+            Action::NewScene { name, desc } => {
+                state.new_scene(name, desc);
+                for message in &messages {
+                    state.append_scene_history(String::clone(message));
+                }
+                continue;
+            }
+            Action::AddToInventory {
+                item,
+                message,
+                quantity,
+                desc,
+                tags,
+            } => {
+                let item = Item::new(&item)
+                    .with_quantity(quantity.max(1))
+                    .with_desc(&desc)
+                    .with_tags(tags);
+                state.add_to_inventory(item);
+                message
+            }
+            Action::RemoveFromInventory { item, message } => {
+                state.remove_from_inventory(&item);
+                message
+            }
+            Action::ChangeQuantity {
+                item,
+                change,
+                message,
+            } => {
+                state.change_quantity(&item, change);
+                message
+            }
+            Action::AddToScene { item, message } => {
+                state.add_to_scene(Item::new(&item));
+                message
+            }
+            Action::ModifyStat {
+                stat,
+                change,
+                message,
+            } => {
+                state.modify_stat(stat, change);
+                message
+            }
+            Action::Information { message } => message,
+            Action::EndGame { message } => {
+                ended = true;
+                message
+            }
+        };
+        state.record_event(&message);
+        state.append_scene_history(message.clone());
+        messages.push(message);
+    }
+
+    // Running out of health ends the game, whether or not the model ended it.
+    if was_alive && state.get_stats().is_dead() && !ended {
+        let message = String::from("Your health has run out. Game over.");
+        state.record_event(&message);
+        state.append_scene_history(message);
+        ended = true;
+    }
+
+    if ended {
+        state.disable_entry();
+    } else {
+        state.enable_entry();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{config::Config, game_loader::backend::MockBackend, model::stats::Stat};
+
+    use super::*;
+
+    /// A worker playing the demo script, saving to a file unique to the test.
+    fn demo_worker(test_name: &str) -> Worker {
+        let save_path = std::env::temp_dir().join(format!("adventui-test-{}.yaml", test_name));
+        let config =
+            Config::from_yaml(&format!("save_path: {}", save_path.to_str().unwrap())).unwrap();
+        let script = fs::read_to_string("scripts/demo.yaml").unwrap();
+        let backend = MockBackend::from_yaml(&script).unwrap();
+        let loader = GameLoader::with_log(&config, Box::new(backend), Box::new(std::io::sink()));
+        Worker::new(loader)
+    }
+
+    fn wait_for_turn(worker: &Worker, state: &mut GameState, mut pending: Option<PendingTurn>) {
+        while pending.is_some() {
+            let event = worker.recv();
+            handle_turn_event(worker, state, &mut pending, event);
+        }
+    }
+
+    fn enter(worker: &Worker, state: &mut GameState, id: u64, input: &str) -> PendingTurn {
+        input.chars().for_each(|c| state.append_entry(c));
+        submit_entry(worker, state, id).expect("The entry was refused.")
+    }
+
+    #[test]
+    fn dispatcher_plays_scripted_game() {
+        let worker = demo_worker("plays-scripted-game");
+        let mut state = GameState::new();
+
+        let turn = start_game(&worker, &mut state, 1, DEFAULT_PACK);
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(state.get_scene_title(), "The Old House");
+        assert!(!state.is_thinking());
+        assert_eq!(
+            state.get_world().get_current().unwrap().get_items(),
+            &[Item::new("Brass Lamp")]
+        );
+
+        let turn = enter(&worker, &mut state, 2, "take the lamp");
+        assert!(state.is_thinking());
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(state.get_inventory(), &vec![Item::new("Brass Lamp")]);
+        assert_eq!(
+            state.get_scene_history().last().unwrap(),
+            "You pick up the brass lamp. It is heavier than it looks."
+        );
+        assert!(state
+            .get_world()
+            .get_current()
+            .unwrap()
+            .get_items()
+            .is_empty());
+
+        let turn = enter(&worker, &mut state, 3, "dance");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(
+            state.get_scene_history().last().unwrap(),
+            "Nothing happens."
+        );
+
+        let turn = enter(&worker, &mut state, 4, "go north");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(state.get_scene_title(), "The Overgrown Yard");
+        assert!(state.get_scene_history().is_empty());
+        assert_eq!(state.get_inventory(), &vec![Item::new("Brass Lamp")]);
+
+        let turn = enter(&worker, &mut state, 5, "go back inside");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(state.get_scene_title(), "The Old House");
+        assert!(state
+            .get_scene_desc()
+            .starts_with("You awake in what appears"));
+        let house = state.get_world().get_current().unwrap();
+        assert_eq!(house.get_exits(), &[String::from("the-overgrown-yard")]);
+        assert_eq!(
+            house.get_events(),
+            &[
+                String::from("A brass lamp sits on a table by the door."),
+                String::from("You pick up the brass lamp. It is heavier than it looks."),
+                String::from("Nothing happens."),
+            ]
+        );
+
+        let turn = enter(&worker, &mut state, 6, "drop the lamp");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert!(state.get_inventory().is_empty());
+        assert_eq!(
+            state.get_world().get_current().unwrap().get_items(),
+            &[Item::new("Brass Lamp")]
+        );
+
+        let turn = enter(&worker, &mut state, 7, "walk into the forest");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        assert_eq!(
+            state.get_scene_history().last().unwrap(),
+            "You follow the path into the forest, and never look back. Thanks for playing!"
+        );
+    }
+
+    #[test]
+    fn dispatcher_ignores_cancelled_turn() {
+        let worker = demo_worker("ignores-cancelled-turn");
+        let mut state = GameState::new();
+        let turn = start_game(&worker, &mut state, 1, DEFAULT_PACK);
+        wait_for_turn(&worker, &mut state, Some(turn));
+
+        // Cancel the turn the way the dispatcher does, by dropping it and restoring the snapshot.
+        let cancelled = enter(&worker, &mut state, 2, "take the lamp");
+        cancelled.cancellation.cancel();
+        let mut state = cancelled.snapshot.unwrap();
+        assert_eq!(state.get_user_entry(), "take the lamp");
+        while !state.get_user_entry().is_empty() {
+            state.remove_last_entry();
+        }
+
+        let turn = enter(&worker, &mut state, 3, "dance");
+        wait_for_turn(&worker, &mut state, Some(turn));
+
+        assert!(state.get_inventory().is_empty());
+        assert_eq!(
+            state.get_scene_history().last().unwrap(),
+            "Nothing happens."
+        );
+    }
+
+    #[test]
+    fn dispatcher_continues_saved_game() {
+        let worker = demo_worker("continues-saved-game");
+        let mut state = GameState::new();
+        let turn = start_game(&worker, &mut state, 1, DEFAULT_PACK);
+        wait_for_turn(&worker, &mut state, Some(turn));
+        let turn = enter(&worker, &mut state, 2, "take the lamp");
+        wait_for_turn(&worker, &mut state, Some(turn));
+        drop(worker);
+
+        let worker = demo_worker("continues-saved-game");
+        let mut resumed = GameState::new();
+        // A cancelled resume is never answered, so the first answer is for the second one.
+        let cancellation = Cancellation::default();
+        cancellation.cancel();
+        worker.send(TurnRequest::Resume {
+            id: 1,
+            cancellation,
+        });
+        resume_game(&worker, &mut resumed, 2);
+        let TurnEvent::Resumed {
+            id: 2,
+            state: Some(saved),
+        } = worker.recv()
+        else {
+            panic!("Expected a saved game for the second resume.");
+        };
+
+        assert_eq!(saved.get_scene_title(), "The Old House");
+        assert_eq!(saved.get_inventory(), &vec![Item::new("Brass Lamp")]);
+        assert_eq!(saved.get_scene_history(), state.get_scene_history());
+    }
+
+    #[test]
+    fn dispatcher_applies_compound_actions() {
+        let mut state = GameState::new();
+        state.disable_entry();
+
+        apply_actions(
+            &mut state,
+            vec![
+                Action::AddToInventory {
+                    item: String::from("Brass Lamp"),
+                    message: String::from("You take the lamp."),
+                    quantity: 1,
+                    desc: String::new(),
+                    tags: Vec::new(),
+                },
+                Action::NewScene {
+                    name: String::from("The Overgrown Yard"),
+                    desc: String::from("Weeds everywhere."),
+                },
+                Action::Information {
+                    message: String::from("The lamp flickers."),
+                },
+            ],
+        );
+
+        assert_eq!(state.get_scene_title(), "The Overgrown Yard");
+        assert_eq!(state.get_inventory(), &vec![Item::new("Brass Lamp")]);
+        assert_eq!(
+            state.get_scene_history(),
+            &vec![
+                String::from("You take the lamp."),
+                String::from("The lamp flickers."),
+            ]
+        );
+        assert!(state.is_entry_enabled());
+
+        apply_actions(
+            &mut state,
+            vec![
+                Action::EndGame {
+                    message: String::from("The lamp goes out."),
+                },
+                Action::Information {
+                    message: String::from("Thanks for playing!"),
+                },
+            ],
+        );
+
+        assert!(!state.is_entry_enabled());
+    }
+
+    #[test]
+    fn dispatcher_examines_items_locally() {
+        let mut state = GameState::new();
+        state.add_to_inventory(Item::new("Brass Lamp").with_desc("A dented brass lamp."));
+        state.add_to_inventory(Item::new("Rope"));
+
+        "Examine the brass lamp"
+            .chars()
+            .for_each(|c| state.append_entry(c));
+        assert!(examine_item(&mut state));
+        assert_eq!(
+            state.get_scene_history(),
+            &vec![
+                String::from("> Examine the brass lamp"),
+                String::from("A dented brass lamp."),
+            ]
+        );
+        assert!(state.get_user_entry().is_empty());
+
+        for entry in ["examine rope", "examine candle", "take the lamp"] {
+            entry.chars().for_each(|c| state.append_entry(c));
+            assert!(!examine_item(&mut state), "entry: {}", entry);
+            while !state.get_user_entry().is_empty() {
+                state.remove_last_entry();
+            }
+        }
+    }
+
+    #[test]
+    fn dispatcher_changes_item_quantities() {
+        let mut state = GameState::new();
+        state.new_scene(String::from("The Ferry"), String::new());
+
+        apply_actions(
+            &mut state,
+            vec![
+                Action::AddToInventory {
+                    item: String::from("Gold Coin"),
+                    message: String::from("You find three coins."),
+                    quantity: 3,
+                    desc: String::from("A worn gold coin."),
+                    tags: vec![String::from("currency")],
+                },
+                Action::ChangeQuantity {
+                    item: String::from("gold coin"),
+                    change: -1,
+                    message: String::from("You pay the ferryman."),
+                },
+                Action::ChangeQuantity {
+                    item: String::from("Arrow"),
+                    change: 5,
+                    message: String::from("You find a bundle of arrows."),
+                },
+                Action::ChangeQuantity {
+                    item: String::from("Rope"),
+                    change: 0,
+                    message: String::from("You have no rope to spare."),
+                },
+            ],
+        );
+
+        assert_eq!(
+            state.get_inventory(),
+            &vec![
+                Item::new("Gold Coin")
+                    .with_quantity(2)
+                    .with_desc("A worn gold coin.")
+                    .with_tags(vec![String::from("currency")]),
+                Item::new("Arrow").with_quantity(5),
+            ]
+        );
+
+        apply_actions(
+            &mut state,
+            vec![
+                Action::ChangeQuantity {
+                    item: String::from("Arrow"),
+                    change: -5,
+                    message: String::from("You shoot your last arrows."),
+                },
+                Action::RemoveFromInventory {
+                    item: String::from("Gold Coin"),
+                    message: String::from("You drop your coins."),
+                },
+            ],
+        );
+
+        assert!(state.get_inventory().is_empty());
+        let dropped = state.get_world().get_current().unwrap().get_items();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].get_quantity(), 2);
+        assert_eq!(dropped[0].get_desc(), "A worn gold coin.");
+    }
+
+    #[test]
+    fn dispatcher_ends_game_when_health_runs_out() {
+        let mut state = GameState::new();
+        let wound = |change| Action::ModifyStat {
+            stat: Stat::Health,
+            change,
+            message: String::from("The wolf bites you."),
+        };
+
+        apply_actions(
+            &mut state,
+            vec![
+                wound(-40),
+                Action::ModifyStat {
+                    stat: Stat::Gold,
+                    change: 10,
+                    message: String::from("You find a purse."),
+                },
+            ],
+        );
+        assert_eq!(state.get_stats().get_health(), 60);
+        assert_eq!(state.get_stats().get_gold(), 10);
+        assert!(state.is_entry_enabled());
+
+        apply_actions(&mut state, vec![wound(-80)]);
+
+        assert!(state.get_stats().is_dead());
+        assert_eq!(
+            state.get_scene_history().last().unwrap(),
+            "Your health has run out. Game over."
+        );
+        assert!(!state.is_entry_enabled());
+
+        let worker = demo_worker("ends-game-when-health-runs-out");
+        "look around".chars().for_each(|c| state.append_entry(c));
+        assert!(submit_entry(&worker, &mut state, 1).is_none());
+        assert!(!state.is_thinking());
+    }
+}
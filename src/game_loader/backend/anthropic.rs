@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::action::Action;
+
+use super::{
+    Backend, BackendError, Cancellation, HttpClient, HttpConfig, ModelProfile, ModelProfiles,
+    Progress, Prompt, Usage,
+};
+
+/// The Messages API requires `max_tokens`, so this is sent for profiles that do not set one.
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnthropicConfig {
+    /// Base URL of any server that speaks the Anthropic `/messages` protocol.
+    base_url: String,
+    /// Falls back to the `ANTHROPIC_API_KEY` environment variable.
+    api_key: Option<String>,
+    /// Sent as the `anthropic-version` header.
+    version: String,
+    /// Extra headers sent with every request.
+    headers: BTreeMap<String, String>,
+    /// The model and sampling parameters for each kind of request. `seed` is not supported by
+    /// the Messages API and is ignored, and `stop` is sent as `stop_sequences`.
+    models: ModelProfiles,
+    /// Streams actions as server-sent events so the narrative can be shown as it arrives.
+    stream: bool,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        AnthropicConfig {
+            base_url: String::from("https://api.anthropic.com/v1"),
+            api_key: None,
+            version: String::from("2023-06-01"),
+            headers: BTreeMap::new(),
+            models: ModelProfiles::new(
+                ModelProfile::new("claude-3-5-sonnet-20240620"),
+                ModelProfile::new("claude-3-haiku-20240307"),
+                ModelProfile::new("claude-3-haiku-20240307"),
+            ),
+            stream: false,
+        }
+    }
+}
+
+pub struct AnthropicBackend {
+    config: AnthropicConfig,
+    api_key: Option<String>,
+    client: HttpClient,
+}
+
+/// The text of a reply, and why the model stopped writing it.
+#[derive(Debug, PartialEq)]
+struct Reply {
+    text: String,
+    stop_reason: Option<String>,
+}
+
+impl AnthropicBackend {
+    pub fn new(config: AnthropicConfig, http: HttpConfig) -> Self {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("ANTHROPIC_API_KEY").ok());
+        AnthropicBackend {
+            config,
+            api_key,
+            client: HttpClient::new(http),
+        }
+    }
+
+    fn request_body(&self, profile: &ModelProfile, prompt: &Prompt) -> Value {
+        let messages: Vec<Value> = prompt
+            .get_messages()
+            .iter()
+            .map(|message| {
+                let message = json!(message);
+                json!({
+                    "role": message["role"],
+                    "content": [{ "type": "text", "text": message["content"] }]
+                })
+            })
+            .collect();
+
+        let mut body = json!({
+            "model": profile.get_model(),
+            "system": prompt.get_system(),
+            "messages": messages,
+            "max_tokens": profile.get_max_tokens().unwrap_or(DEFAULT_MAX_TOKENS)
+        });
+        if let Some(temperature) = profile.get_temperature() {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = profile.get_top_p() {
+            body["top_p"] = json!(top_p);
+        }
+        if !profile.get_stop().is_empty() {
+            body["stop_sequences"] = json!(profile.get_stop());
+        }
+        body
+    }
+
+    /// Sends a messages request and returns the text of the reply. When `stream` is set the reply
+    /// is streamed, and `on_progress` receives the text so far.
+    fn send_request(
+        &self,
+        mut body: Value,
+        stream: bool,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Reply, BackendError> {
+        if stream {
+            body["stream"] = json!(true);
+        }
+        let model = body["model"].as_str().map(String::from).unwrap_or_default();
+
+        let url = format!("{}/messages", self.config.base_url.trim_end_matches('/'));
+        let body = body.to_string();
+        let response = self.client.send(
+            |client| {
+                let mut request = client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .header("anthropic-version", &self.config.version)
+                    .body(body.clone());
+                if let Some(api_key) = &self.api_key {
+                    request = request.header("x-api-key", api_key);
+                }
+                for (name, value) in &self.config.headers {
+                    request = request.header(name, value);
+                }
+                request
+            },
+            cancellation,
+            on_progress,
+        )?;
+
+        let reply = if stream {
+            read_event_stream(BufReader::new(response), &model, cancellation, on_progress)?
+        } else {
+            let response_json: Value = response.json()?;
+            if let Some(usage) = parse_usage(&response_json["usage"], &model) {
+                on_progress(Progress::Usage(usage));
+            }
+            Reply {
+                text: text_blocks(&response_json["content"]),
+                stop_reason: response_json["stop_reason"].as_str().map(String::from),
+            }
+        };
+
+        if reply.text.is_empty() {
+            return Err(BackendError::MissingContent);
+        }
+        Ok(reply)
+    }
+}
+
+/// Joins the text blocks of a reply's content, skipping any other kind of block.
+fn text_blocks(content: &Value) -> String {
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect()
+}
+
+fn parse_usage(usage: &Value, model: &str) -> Option<Usage> {
+    Some(Usage::new(
+        model,
+        usage["input_tokens"].as_u64()?,
+        usage["output_tokens"].as_u64()?,
+    ))
+}
+
+/// Reads a server-sent event stream of message events, accumulating the text deltas. The input
+/// tokens arrive with `message_start`, and the output tokens and stop reason with
+/// `message_delta`. Reading stops if the turn is cancelled.
+fn read_event_stream(
+    reader: impl BufRead,
+    model: &str,
+    cancellation: &Cancellation,
+    on_progress: &mut dyn FnMut(Progress),
+) -> Result<Reply, BackendError> {
+    let mut text = String::new();
+    let mut stop_reason = None;
+    let mut input_tokens = None;
+    let mut output_tokens = None;
+    for line in reader.lines() {
+        cancellation.check()?;
+        let Ok(line) = line else {
+            break;
+        };
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            continue;
+        };
+        match event["type"].as_str() {
+            Some("message_start") => {
+                input_tokens = event["message"]["usage"]["input_tokens"].as_u64();
+            }
+            Some("content_block_delta") => {
+                if let Some(delta) = event["delta"]["text"].as_str() {
+                    text += delta;
+                    on_progress(Progress::Partial(text.clone()));
+                }
+            }
+            Some("message_delta") => {
+                stop_reason = event["delta"]["stop_reason"].as_str().map(String::from);
+                output_tokens = event["usage"]["output_tokens"].as_u64();
+            }
+            Some("message_stop") => break,
+            _ => {}
+        }
+    }
+
+    if let (Some(input_tokens), Some(output_tokens)) = (input_tokens, output_tokens) {
+        on_progress(Progress::Usage(Usage::new(
+            model,
+            input_tokens,
+            output_tokens,
+        )));
+    }
+    Ok(Reply { text, stop_reason })
+}
+
+impl Backend for AnthropicBackend {
+    fn name(&self) -> &str {
+        "Anthropic"
+    }
+
+    fn action_model(&self) -> Option<&str> {
+        Some(self.config.models.get_turn().get_model())
+    }
+
+    fn generate_overview(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        let body = self.request_body(self.config.models.get_world(), prompt);
+        Ok(self
+            .send_request(body, false, prompt.get_cancellation(), on_progress)?
+            .text)
+    }
+
+    fn generate_summary(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        let body = self.request_body(self.config.models.get_summary(), prompt);
+        Ok(self
+            .send_request(body, false, prompt.get_cancellation(), on_progress)?
+            .text)
+    }
+
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        let body = self.request_body(self.config.models.get_turn(), prompt);
+        let reply = self.send_request(
+            body,
+            self.config.stream,
+            prompt.get_cancellation(),
+            on_progress,
+        )?;
+
+        match Action::parse_all(&reply.text) {
+            Ok(actions) => Ok(actions),
+            Err(e) => {
+                let mut error = e.to_string();
+                if reply.stop_reason.as_deref() == Some("max_tokens") {
+                    error += " (the reply was cut off by max_tokens)";
+                }
+                Err(BackendError::Parse {
+                    output: reply.text,
+                    error,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::game_loader::backend::{
+        stub_server::{StubResponse, StubServer},
+        ChatMessage,
+    };
+
+    use super::*;
+
+    /// A reply recorded from the Messages API.
+    const RECORDED_MESSAGE: &str = r#"{
+        "id": "msg_01XFDUDYJgAACzvnptvVoYEL",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-3-haiku-20240307",
+        "content": [
+            {"type": "text", "text": "type: AddToInventory\nitem: Brass Lamp\nmessage: You lift the lamp from the hook."}
+        ],
+        "stop_reason": "end_turn",
+        "stop_sequence": null,
+        "usage": {"input_tokens": 412, "output_tokens": 24}
+    }"#;
+
+    /// A reply recorded from the Messages API that ran out of tokens.
+    const RECORDED_TRUNCATED_MESSAGE: &str = r#"{
+        "id": "msg_01Aq9w938a90dw8q",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-3-haiku-20240307",
+        "content": [{"type": "text", "text": "type: NewScene\nname: The Cellar\ndesc: \"Stone steps lead"}],
+        "stop_reason": "max_tokens",
+        "stop_sequence": null,
+        "usage": {"input_tokens": 412, "output_tokens": 16}
+    }"#;
+
+    /// A streamed reply recorded from the Messages API.
+    const RECORDED_STREAM: &str = concat!(
+        "event: message_start\n",
+        "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_014p7gG3wDgGV9EUtLvnow3U\",\"type\":\"message\",\"role\":\"assistant\",\"model\":\"claude-3-haiku-20240307\",\"content\":[],\"stop_reason\":null,\"usage\":{\"input_tokens\":472,\"output_tokens\":2}}}\n",
+        "\n",
+        "event: content_block_start\n",
+        "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n",
+        "\n",
+        "event: ping\n",
+        "data: {\"type\":\"ping\"}\n",
+        "\n",
+        "event: content_block_delta\n",
+        "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"type: Information\\n\"}}\n",
+        "\n",
+        "event: content_block_delta\n",
+        "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"message: Hello\"}}\n",
+        "\n",
+        "event: content_block_stop\n",
+        "data: {\"type\":\"content_block_stop\",\"index\":0}\n",
+        "\n",
+        "event: message_delta\n",
+        "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":15}}\n",
+        "\n",
+        "event: message_stop\n",
+        "data: {\"type\":\"message_stop\"}\n",
+    );
+
+    fn backend_for(server: &StubServer, stream: bool) -> AnthropicBackend {
+        let config = AnthropicConfig {
+            base_url: format!("{}/v1", server.url()),
+            api_key: Some(String::from("test-key")),
+            stream,
+            ..AnthropicConfig::default()
+        };
+        AnthropicBackend::new(config, HttpConfig::default())
+    }
+
+    fn prompt() -> Prompt {
+        Prompt::new(
+            String::from("You are a game."),
+            vec![ChatMessage::user(String::from("take the lamp"))],
+        )
+    }
+
+    #[test]
+    fn anthropic_backend_generates_action_from_recorded_reply() {
+        let server = StubServer::start(vec![StubResponse::new(200, RECORDED_MESSAGE)]);
+        let mut backend = backend_for(&server, false);
+
+        let mut usages = Vec::new();
+        let actions = backend
+            .generate_action(&prompt(), &mut |progress| {
+                if let Progress::Usage(usage) = progress {
+                    usages.push(usage);
+                }
+            })
+            .unwrap();
+
+        let expected = vec![Action::AddToInventory {
+            item: String::from("Brass Lamp"),
+            message: String::from("You lift the lamp from the hook."),
+            quantity: 1,
+            desc: String::new(),
+            tags: Vec::new(),
+        }];
+        assert_eq!(actions, expected);
+        assert_eq!(usages, vec![Usage::new("claude-3-haiku-20240307", 412, 24)]);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/v1/messages");
+        assert_eq!(request.header("x-api-key"), Some("test-key"));
+        assert_eq!(request.header("anthropic-version"), Some("2023-06-01"));
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["model"], "claude-3-haiku-20240307");
+        assert_eq!(body["system"], "You are a game.");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(
+            body["messages"],
+            json!([{ "role": "user", "content": [{ "type": "text", "text": "take the lamp" }] }])
+        );
+    }
+
+    #[test]
+    fn anthropic_backend_reports_truncated_reply() {
+        let server = StubServer::start(vec![StubResponse::new(200, RECORDED_TRUNCATED_MESSAGE)]);
+        let mut backend = backend_for(&server, false);
+
+        let result = backend.generate_action(&prompt(), &mut |_| {});
+
+        let Err(BackendError::Parse { error, .. }) = result else {
+            panic!("Expected a parse error, got {:?}", result);
+        };
+        assert!(error.ends_with("(the reply was cut off by max_tokens)"));
+    }
+
+    #[test]
+    fn anthropic_backend_streams_recorded_reply() {
+        let server = StubServer::start(vec![StubResponse::new(200, RECORDED_STREAM)]);
+        let mut backend = backend_for(&server, true);
+
+        let mut partials = Vec::new();
+        let actions = backend
+            .generate_action(&prompt(), &mut |progress| {
+                if let Progress::Partial(text) = progress {
+                    partials.push(text);
+                }
+            })
+            .unwrap();
+
+        let expected = vec![Action::Information {
+            message: String::from("Hello"),
+        }];
+        assert_eq!(actions, expected);
+        assert_eq!(
+            partials,
+            vec!["type: Information\n", "type: Information\nmessage: Hello"]
+        );
+        let body: Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+    }
+
+    #[test]
+    fn anthropic_read_event_stream_usage_and_stop_reason() {
+        let mut usages = Vec::new();
+
+        let reply = read_event_stream(
+            Cursor::new(RECORDED_STREAM),
+            "model",
+            &Cancellation::default(),
+            &mut |progress| {
+                if let Progress::Usage(usage) = progress {
+                    usages.push(usage);
+                }
+            },
+        )
+        .unwrap();
+
+        assert_eq!(reply.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(usages, vec![Usage::new("model", 472, 15)]);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::action::Action;
+
+use super::{Backend, BackendError, Progress, Prompt, ResponseFormat};
+
+/// Tries an ordered list of backends in turn, falling through to the next one when a backend is
+/// unreachable, fails on the server side or replies with an action that cannot be parsed. Any
+/// other error, such as a rejected API key, is returned as it is.
+pub struct FallbackBackend {
+    backends: Vec<Box<dyn Backend>>,
+    /// The backend that answered the last request, or failed it last.
+    current: usize,
+}
+
+impl FallbackBackend {
+    pub fn new(backends: Vec<Box<dyn Backend>>) -> Self {
+        assert!(!backends.is_empty(), "A fallback chain needs a backend.");
+        FallbackBackend {
+            backends,
+            current: 0,
+        }
+    }
+
+    /// Calls `generate` with each backend until one succeeds or fails with an error that another
+    /// backend would not fix. Each fall through is reported through `on_progress`.
+    fn generate<T>(
+        &mut self,
+        on_progress: &mut dyn FnMut(Progress),
+        mut generate: impl FnMut(&mut dyn Backend, &mut dyn FnMut(Progress)) -> Result<T, BackendError>,
+    ) -> Result<T, BackendError> {
+        for index in 0..self.backends.len() {
+            self.current = index;
+            let result = generate(self.backends[index].as_mut(), on_progress);
+            match (result, self.backends.get(index + 1)) {
+                (Err(e), Some(next)) if should_fall_back(&e) => {
+                    on_progress(Progress::Fallback {
+                        failed: String::from(self.backends[index].name()),
+                        next: String::from(next.name()),
+                        error: e.to_string(),
+                    });
+                }
+                (result, _) => return result,
+            }
+        }
+        unreachable!("The last backend always returns.")
+    }
+}
+
+fn should_fall_back(error: &BackendError) -> bool {
+    match error {
+        BackendError::Request(e) => e.is_timeout() || e.is_connect(),
+        BackendError::Status(status) => status.is_server_error(),
+        BackendError::Parse { .. } => true,
+        BackendError::MissingContent | BackendError::Cancelled => false,
+    }
+}
+
+impl Backend for FallbackBackend {
+    /// The name of the backend that answered the last request.
+    fn name(&self) -> &str {
+        self.backends[self.current].name()
+    }
+
+    /// The first backend's name, since every request is sent to it first.
+    fn request_name(&self) -> &str {
+        self.backends[0].name()
+    }
+
+    /// The first backend's model, since that is the one expected to answer.
+    fn action_model(&self) -> Option<&str> {
+        self.backends[0].action_model()
+    }
+
+    /// The first backend's format, since the turn prompt is written for it.
+    fn response_format(&self) -> ResponseFormat {
+        self.backends[0].response_format()
+    }
+
+    fn generate_overview(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        self.generate(on_progress, |backend, on_progress| {
+            backend.generate_overview(prompt, on_progress)
+        })
+    }
+
+    fn generate_summary(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        self.generate(on_progress, |backend, on_progress| {
+            backend.generate_summary(prompt, on_progress)
+        })
+    }
+
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        self.generate(on_progress, |backend, on_progress| {
+            backend.generate_action(prompt, on_progress)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    /// Fails every request with the given error, or answers them if there is none.
+    struct FixedBackend {
+        name: &'static str,
+        error: Option<fn() -> BackendError>,
+    }
+
+    impl Backend for FixedBackend {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn generate_overview(
+            &mut self,
+            _prompt: &Prompt,
+            _on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<String, BackendError> {
+            match self.error {
+                Some(error) => Err(error()),
+                None => Ok(format!("An overview from {}.", self.name)),
+            }
+        }
+
+        fn generate_summary(
+            &mut self,
+            prompt: &Prompt,
+            on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<String, BackendError> {
+            self.generate_overview(prompt, on_progress)
+        }
+
+        fn generate_action(
+            &mut self,
+            _prompt: &Prompt,
+            _on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<Vec<Action>, BackendError> {
+            match self.error {
+                Some(error) => Err(error()),
+                None => Ok(vec![Action::Information {
+                    message: format!("An answer from {}.", self.name),
+                }]),
+            }
+        }
+    }
+
+    fn chain(backends: Vec<FixedBackend>) -> FallbackBackend {
+        FallbackBackend::new(
+            backends
+                .into_iter()
+                .map(|backend| Box::new(backend) as Box<dyn Backend>)
+                .collect(),
+        )
+    }
+
+    fn prompt() -> Prompt {
+        Prompt::new(String::new(), Vec::new())
+    }
+
+    #[test]
+    fn fallback_falls_through_server_errors_and_unparseable_actions() {
+        let mut backend = chain(vec![
+            FixedBackend {
+                name: "Primary",
+                error: Some(|| BackendError::Status(StatusCode::SERVICE_UNAVAILABLE)),
+            },
+            FixedBackend {
+                name: "Secondary",
+                error: Some(|| BackendError::Parse {
+                    output: String::from("type: Dance"),
+                    error: String::from("unknown variant `Dance`"),
+                }),
+            },
+            FixedBackend {
+                name: "Tertiary",
+                error: None,
+            },
+        ]);
+        let mut fallbacks = Vec::new();
+
+        let actions = backend
+            .generate_action(&prompt(), &mut |progress| {
+                if let Progress::Fallback { failed, next, .. } = progress {
+                    fallbacks.push((failed, next));
+                }
+            })
+            .unwrap();
+
+        assert_eq!(
+            actions,
+            vec![Action::Information {
+                message: String::from("An answer from Tertiary."),
+            }]
+        );
+        assert_eq!(backend.name(), "Tertiary");
+        assert_eq!(backend.request_name(), "Primary");
+        assert_eq!(
+            fallbacks,
+            vec![
+                (String::from("Primary"), String::from("Secondary")),
+                (String::from("Secondary"), String::from("Tertiary")),
+            ]
+        );
+    }
+
+    #[test]
+    fn fallback_returns_client_errors_and_last_error() {
+        let mut backend = chain(vec![
+            FixedBackend {
+                name: "Primary",
+                error: Some(|| BackendError::Status(StatusCode::UNAUTHORIZED)),
+            },
+            FixedBackend {
+                name: "Secondary",
+                error: None,
+            },
+        ]);
+
+        let result = backend.generate_overview(&prompt(), &mut |_| {});
+        assert!(matches!(
+            result,
+            Err(BackendError::Status(StatusCode::UNAUTHORIZED))
+        ));
+        assert_eq!(backend.name(), "Primary");
+
+        let mut backend = chain(vec![
+            FixedBackend {
+                name: "Primary",
+                error: Some(|| BackendError::Status(StatusCode::BAD_GATEWAY)),
+            },
+            FixedBackend {
+                name: "Secondary",
+                error: Some(|| BackendError::Status(StatusCode::SERVICE_UNAVAILABLE)),
+            },
+        ]);
+
+        let result = backend.generate_summary(&prompt(), &mut |_| {});
+        assert!(matches!(
+            result,
+            Err(BackendError::Status(StatusCode::SERVICE_UNAVAILABLE))
+        ));
+    }
+}
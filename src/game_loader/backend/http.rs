@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::RETRY_AFTER,
+    StatusCode,
+};
+use serde::Deserialize;
+
+use super::{BackendError, Cancellation, Progress};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Seconds to wait for a connection to the backend.
+    connect_timeout_secs: u64,
+    /// Seconds to wait on any single read, including each chunk of a streamed reply.
+    read_timeout_secs: u64,
+    /// How many times a request is retried after a 429 or 5xx response.
+    max_retries: u32,
+    /// Delay before the first retry, doubled for each retry after it. A `Retry-After` header from
+    /// the server takes precedence.
+    initial_backoff_ms: u64,
+    /// Upper bound for any single retry delay, including one requested through `Retry-After`.
+    max_backoff_ms: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 120,
+            max_retries: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30000,
+        }
+    }
+}
+
+/// A client shared by all requests of a backend, which retries rate limited and failed requests
+/// with exponential backoff.
+pub struct HttpClient {
+    client: Client,
+    config: HttpConfig,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Self {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.read_timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client.");
+        HttpClient { client, config }
+    }
+
+    /// Sends the request built by `request` until it succeeds, fails with a status that is not
+    /// worth retrying, or runs out of retries. Each retry is reported through `on_progress`. Once
+    /// `cancellation` is set, no further attempts are made.
+    pub fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Response, BackendError> {
+        let mut attempt = 0;
+        loop {
+            cancellation.check()?;
+            let response = request(&self.client).send()?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retryable || attempt >= self.config.max_retries {
+                return Err(BackendError::Status(status));
+            }
+
+            attempt += 1;
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            let delay = self.retry_delay(attempt, retry_after);
+            on_progress(Progress::Retrying {
+                status,
+                attempt,
+                max_retries: self.config.max_retries,
+                delay,
+            });
+            cancellation.sleep(delay)?;
+        }
+    }
+
+    fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = Duration::from_millis(
+            self.config
+                .initial_backoff_ms
+                .saturating_mul(2u64.saturating_pow(attempt - 1)),
+        );
+        retry_after
+            .unwrap_or(backoff)
+            .min(Duration::from_millis(self.config.max_backoff_ms))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game_loader::backend::stub_server::{StubResponse, StubServer};
+
+    use super::*;
+
+    fn test_client(max_retries: u32) -> HttpClient {
+        HttpClient::new(HttpConfig {
+            connect_timeout_secs: 1,
+            read_timeout_secs: 1,
+            max_retries,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 10,
+        })
+    }
+
+    fn send(client: &HttpClient, server: &StubServer) -> (Result<Response, BackendError>, u32) {
+        let mut retries = 0;
+        let result = client.send(
+            |client| client.post(server.url()).body("{}"),
+            &Cancellation::default(),
+            &mut |progress| {
+                if let Progress::Retrying { .. } = progress {
+                    retries += 1;
+                }
+            },
+        );
+        (result, retries)
+    }
+
+    #[test]
+    fn http_retries_rate_limits_and_server_errors() {
+        let server = StubServer::start(vec![
+            StubResponse::new(429, "{}").with_header("Retry-After", "0"),
+            StubResponse::new(503, "{}"),
+            StubResponse::new(200, r#"{"ok": true}"#),
+        ]);
+
+        let (result, retries) = send(&test_client(3), &server);
+
+        assert_eq!(result.unwrap().text().unwrap(), r#"{"ok": true}"#);
+        assert_eq!(retries, 2);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn http_stops_retrying_once_cancelled() {
+        let server = StubServer::start(vec![
+            StubResponse::new(503, "{}").with_header("Retry-After", "30"),
+            StubResponse::new(200, "{}"),
+        ]);
+        let client = HttpClient::new(HttpConfig {
+            max_backoff_ms: 30000,
+            ..HttpConfig::default()
+        });
+        let cancellation = Cancellation::default();
+
+        let result = client.send(
+            |client| client.post(server.url()).body("{}"),
+            &cancellation,
+            &mut |_| cancellation.cancel(),
+        );
+
+        assert!(matches!(result, Err(BackendError::Cancelled)));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn http_gives_up_after_max_retries() {
+        let server = StubServer::start(vec![
+            StubResponse::new(500, "{}"),
+            StubResponse::new(502, "{}"),
+            StubResponse::new(200, "{}"),
+        ]);
+
+        let (result, retries) = send(&test_client(1), &server);
+
+        assert!(matches!(
+            result,
+            Err(BackendError::Status(StatusCode::BAD_GATEWAY))
+        ));
+        assert_eq!(retries, 1);
+    }
+
+    #[test]
+    fn http_does_not_retry_client_errors() {
+        let server = StubServer::start(vec![
+            StubResponse::new(401, "{}"),
+            StubResponse::new(200, "{}"),
+        ]);
+
+        let (result, retries) = send(&test_client(3), &server);
+
+        assert!(matches!(
+            result,
+            Err(BackendError::Status(StatusCode::UNAUTHORIZED))
+        ));
+        assert_eq!(retries, 0);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn http_times_out_slow_responses() {
+        let server = StubServer::start(vec![
+            StubResponse::new(200, "{}").with_delay(Duration::from_millis(1500))
+        ]);
+
+        let (result, _) = send(&test_client(0), &server);
+
+        match result {
+            Err(BackendError::Request(e)) => assert!(e.is_timeout()),
+            _ => panic!("Expected a timeout."),
+        }
+    }
+
+    #[test]
+    fn http_retry_delay_backs_off_exponentially() {
+        let client = HttpClient::new(HttpConfig::default());
+
+        assert_eq!(client.retry_delay(1, None), Duration::from_secs(1));
+        assert_eq!(client.retry_delay(3, None), Duration::from_secs(4));
+        assert_eq!(client.retry_delay(10, None), Duration::from_secs(30));
+        assert_eq!(
+            client.retry_delay(3, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            client.retry_delay(1, Some(Duration::from_secs(600))),
+            Duration::from_secs(30)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::action::{one_or_many, Action};
+
+use super::{Backend, BackendError, Progress, Prompt};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MockConfig {
+    /// Path to the YAML script the backend answers from.
+    script: String,
+}
+
+/// A backend that answers from a script of `input -> Action` entries instead of an LLM, for
+/// offline play and deterministic tests. The first entry matching the player's input wins.
+#[derive(Debug, Deserialize)]
+pub struct MockBackend {
+    #[serde(default = "default_overview")]
+    overview: String,
+    #[serde(default = "default_summary")]
+    summary: String,
+    #[serde(default)]
+    entries: Vec<ScriptEntry>,
+    #[serde(default = "default_fallback")]
+    fallback: Action,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptEntry {
+    /// A case-insensitive regex matched against the input.
+    pattern: Option<Pattern>,
+    /// Words that must all appear in the input, in any order and any case.
+    #[serde(default)]
+    keywords: Vec<String>,
+    /// A single action, or a list of actions for a turn with several effects.
+    #[serde(deserialize_with = "one_or_many::deserialize")]
+    action: Vec<Action>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map(Pattern)
+    }
+}
+
+fn default_overview() -> String {
+    String::from("A scripted adventure used for offline play and testing.")
+}
+
+fn default_summary() -> String {
+    String::from("The player has been exploring.")
+}
+
+fn default_fallback() -> Action {
+    Action::Information {
+        message: String::from("Nothing happens."),
+    }
+}
+
+impl ScriptEntry {
+    fn matches(&self, input: &str) -> bool {
+        if self.pattern.is_none() && self.keywords.is_empty() {
+            return false;
+        }
+
+        let input = input.to_lowercase();
+        let words: Vec<&str> = input
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let pattern_matches = match &self.pattern {
+            Some(Pattern(regex)) => regex.is_match(&input),
+            None => true,
+        };
+        let keywords_match = self
+            .keywords
+            .iter()
+            .all(|keyword| words.contains(&keyword.to_lowercase().as_str()));
+
+        pattern_matches && keywords_match
+    }
+}
+
+impl MockBackend {
+    pub fn new(config: MockConfig) -> Self {
+        let script = fs::read_to_string(&config.script).expect("Failed to read mock script.");
+        MockBackend::from_yaml(&script).expect("Failed to parse mock script.")
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    fn generate_overview(
+        &mut self,
+        _prompt: &Prompt,
+        _on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        Ok(self.overview.clone())
+    }
+
+    fn generate_summary(
+        &mut self,
+        _prompt: &Prompt,
+        _on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        Ok(self.summary.clone())
+    }
+
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        _on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        let input = prompt.get_input().unwrap_or_default();
+        let actions = self
+            .entries
+            .iter()
+            .find(|entry| entry.matches(input))
+            .map(|entry| entry.action.clone())
+            .unwrap_or(vec![self.fallback.clone()]);
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prompt_for(input: &str) -> Prompt {
+        Prompt::new(String::new(), Vec::new()).with_input(String::from(input))
+    }
+
+    #[test]
+    fn mock_matches_pattern_keywords_and_fallback() {
+        let yaml = r#"
+            entries:
+              - pattern: "^start game$"
+                action:
+                  type: NewScene
+                  name: The Old House
+                  desc: You wake up in an old house.
+              - keywords: [take, lamp]
+                action:
+                  type: AddToInventory
+                  item: Lamp
+                  message: You take the lamp.
+              - keywords: [light, lamp]
+                action:
+                  - type: Information
+                    message: The lamp flickers on.
+                  - type: NewScene
+                    name: The Lit Hall
+                    desc: Light floods the hall.
+        "#;
+        let mut backend = MockBackend::from_yaml(yaml).unwrap();
+
+        let mut generate = |input: &str| {
+            backend
+                .generate_action(&prompt_for(input), &mut |_| {})
+                .unwrap()
+        };
+
+        let actual = generate("Start Game");
+        assert!(matches!(actual[..], [Action::NewScene { .. }]));
+
+        let actual = generate("take the LAMP!");
+        assert!(matches!(actual[..], [Action::AddToInventory { .. }]));
+
+        let actual = generate("light the lamp");
+        assert!(matches!(
+            actual[..],
+            [Action::Information { .. }, Action::NewScene { .. }]
+        ));
+
+        let actual = generate("lamp");
+        assert_eq!(actual, vec![default_fallback()]);
+    }
+
+    #[test]
+    fn mock_rejects_invalid_pattern() {
+        let yaml = r#"
+            entries:
+              - pattern: "(unclosed"
+                action:
+                  type: Information
+                  message: Never matched.
+        "#;
+
+        assert!(MockBackend::from_yaml(yaml).is_err());
+    }
+}
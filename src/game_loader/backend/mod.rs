@@ -0,0 +1,323 @@
+// Backends are responsible for talking to a specific LLM provider and turning its replies into game content
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::action::Action;
+
+pub use self::anthropic::{AnthropicBackend, AnthropicConfig};
+pub use self::fallback::FallbackBackend;
+pub use self::http::{HttpClient, HttpConfig};
+pub use self::mock::{MockBackend, MockConfig};
+pub use self::openai::{OpenAiBackend, OpenAiConfig};
+pub use self::profile::{ModelProfile, ModelProfiles};
+pub use self::template::{TemplateBackend, TemplateConfig};
+
+mod anthropic;
+mod fallback;
+mod http;
+mod mock;
+mod openai;
+mod profile;
+#[cfg(test)]
+mod stub_server;
+mod template;
+
+pub trait Backend: Send {
+    /// A short human readable name for the backend, used in logs.
+    fn name(&self) -> &str;
+
+    /// The name of the backend requests are sent to first, used when logging them. Only backends
+    /// that pass requests on to others have a different one.
+    fn request_name(&self) -> &str {
+        self.name()
+    }
+
+    /// The model actions are generated with, if the backend uses one, for looking up its
+    /// context budget.
+    fn action_model(&self) -> Option<&str> {
+        None
+    }
+
+    /// How the backend asks the model for actions, which the turn prompt describes.
+    fn response_format(&self) -> ResponseFormat {
+        ResponseFormat::Yaml
+    }
+
+    /// Generates the free-form overview of a new game.
+    fn generate_overview(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError>;
+
+    /// Generates a summary of the story so far, for the game's memory.
+    fn generate_summary(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError>;
+
+    /// Generates the actions for the next turn in response to the context in the prompt, which
+    /// is usually a single action, and several when the model calls more than one tool. Backends
+    /// that stream their replies report the text received so far through `on_progress`, along
+    /// with any retries.
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError>;
+}
+
+/// The shape the model is asked to give its actions in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    /// A YAML list of actions in the reply text.
+    #[default]
+    Yaml,
+    /// One tool call per action.
+    Tools,
+    /// A JSON object matching `Action::json_schema`.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// The raw reply received so far.
+    Partial(String),
+    /// The request failed with a status worth retrying, and will be retried after `delay`.
+    Retrying {
+        status: reqwest::StatusCode,
+        attempt: u32,
+        max_retries: u32,
+        delay: Duration,
+    },
+    /// The tokens used by a request, once it has completed.
+    Usage(Usage),
+    /// The `failed` backend could not answer, and the request is being sent to `next` instead.
+    Fallback {
+        failed: String,
+        next: String,
+        error: String,
+    },
+}
+
+/// The tokens a single request used, as reported by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    model: String,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn new(model: &str, prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Usage {
+            model: String::from(model),
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn get_prompt_tokens(&self) -> u64 {
+        self.prompt_tokens
+    }
+
+    pub fn get_completion_tokens(&self) -> u64 {
+        self.completion_tokens
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Progress::Partial(text) => write!(f, "{}", text),
+            Progress::Retrying {
+                status,
+                attempt,
+                max_retries,
+                delay,
+            } => write!(
+                f,
+                "{}, retrying in {}s (attempt {} of {})",
+                status.canonical_reason().unwrap_or("Request failed"),
+                delay.as_secs_f32().ceil(),
+                attempt,
+                max_retries
+            ),
+            Progress::Usage(usage) => write!(
+                f,
+                "{} used {} prompt and {} completion tokens",
+                usage.model, usage.prompt_tokens, usage.completion_tokens
+            ),
+            Progress::Fallback { failed, next, .. } => {
+                write!(f, "{} failed, trying {}", failed, next)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ChatMessage {
+    role: Role,
+    content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: String) -> Self {
+        ChatMessage {
+            role: Role::User,
+            content,
+        }
+    }
+
+    pub fn assistant(content: String) -> Self {
+        ChatMessage {
+            role: Role::Assistant,
+            content,
+        }
+    }
+}
+
+/// Set when the turn a request belongs to is cancelled, so that backends stop retrying it or
+/// reading its reply instead of holding up the next turn. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with `BackendError::Cancelled` once cancelled.
+    pub fn check(&self) -> Result<(), BackendError> {
+        match self.is_cancelled() {
+            true => Err(BackendError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Sleeps for `delay`, waking up early if cancelled in the meantime.
+    pub fn sleep(&self, delay: Duration) -> Result<(), BackendError> {
+        let until = Instant::now() + delay;
+        loop {
+            self.check()?;
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(left.min(Duration::from_millis(100)));
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Prompt {
+    system: String,
+    messages: Vec<ChatMessage>,
+    /// The raw player input this prompt responds to, if any.
+    #[serde(skip)]
+    input: Option<String>,
+    #[serde(skip)]
+    cancellation: Cancellation,
+}
+
+impl Prompt {
+    pub fn new(system: String, messages: Vec<ChatMessage>) -> Self {
+        Prompt {
+            system,
+            messages,
+            input: None,
+            cancellation: Cancellation::default(),
+        }
+    }
+
+    pub fn with_input(mut self, input: String) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    pub fn push_message(&mut self, message: ChatMessage) {
+        self.messages.push(message);
+    }
+
+    pub fn get_system(&self) -> &str {
+        &self.system
+    }
+
+    pub fn get_messages(&self) -> &Vec<ChatMessage> {
+        &self.messages
+    }
+
+    pub fn get_input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn get_cancellation(&self) -> &Cancellation {
+        &self.cancellation
+    }
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    Request(reqwest::Error),
+    Status(reqwest::StatusCode),
+    MissingContent,
+    Parse {
+        output: String,
+        error: String,
+    },
+    /// The turn the request belongs to was cancelled.
+    Cancelled,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Request(e) => write!(f, "Failed to send request: {}", e),
+            BackendError::Status(status) => {
+                write!(f, "Failed to get a successful response: {}", status)
+            }
+            BackendError::MissingContent => write!(f, "Response did not contain any content."),
+            BackendError::Parse { output, error } => {
+                write!(f, "Failed to parse response {:?}: {}", output, error)
+            }
+            BackendError::Cancelled => write!(f, "The request was cancelled."),
+        }
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(e: reqwest::Error) -> Self {
+        BackendError::Request(e)
+    }
+}
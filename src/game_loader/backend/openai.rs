@@ -0,0 +1,505 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::action::Action;
+
+use super::{
+    Backend, BackendError, Cancellation, HttpClient, HttpConfig, ModelProfile, ModelProfiles,
+    Progress, Prompt, ResponseFormat, Usage,
+};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct OpenAiConfig {
+    /// Base URL of any server that speaks the OpenAI `/chat/completions` protocol.
+    base_url: String,
+    /// Falls back to the `OPENAI_API_KEY` environment variable. Local servers usually need neither.
+    api_key: Option<String>,
+    /// Extra headers sent with every request.
+    headers: BTreeMap<String, String>,
+    /// The model and sampling parameters for each kind of request.
+    models: ModelProfiles,
+    /// Requests actions as JSON matching `Action::json_schema` through `response_format`, for
+    /// servers that support structured output. Otherwise actions are requested as YAML.
+    structured_output: bool,
+    /// Streams actions as server-sent events so the narrative can be shown as it arrives.
+    stream: bool,
+    /// Offers each action type as a tool, so the model takes actions by calling tools, and can
+    /// take several at once through parallel tool calls. Replies are not streamed in this mode.
+    tools: bool,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        OpenAiConfig {
+            base_url: String::from("https://api.openai.com/v1"),
+            api_key: None,
+            headers: BTreeMap::new(),
+            models: ModelProfiles::default(),
+            structured_output: false,
+            stream: false,
+            tools: false,
+        }
+    }
+}
+
+pub struct OpenAiBackend {
+    config: OpenAiConfig,
+    api_key: Option<String>,
+    client: HttpClient,
+}
+
+impl OpenAiBackend {
+    pub fn new(config: OpenAiConfig, http: HttpConfig) -> Self {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("OPENAI_API_KEY").ok());
+        OpenAiBackend {
+            config,
+            api_key,
+            client: HttpClient::new(http),
+        }
+    }
+
+    fn request_body(&self, profile: &ModelProfile, prompt: &Prompt) -> Value {
+        let mut messages = vec![json!({
+            "role": "system",
+            "content": prompt.get_system()
+        })];
+        messages.extend(prompt.get_messages().iter().map(|message| json!(message)));
+
+        let mut body = json!({
+            "model": profile.get_model(),
+            "messages": messages
+        });
+        if let Some(temperature) = profile.get_temperature() {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = profile.get_top_p() {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(max_tokens) = profile.get_max_tokens() {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if let Some(seed) = profile.get_seed() {
+            body["seed"] = json!(seed);
+        }
+        if !profile.get_stop().is_empty() {
+            body["stop"] = json!(profile.get_stop());
+        }
+        body
+    }
+
+    /// Sends a chat completion request and returns the message of the reply. When `stream` is set
+    /// the reply is streamed, and `on_progress` receives the text so far.
+    fn send_request(
+        &self,
+        mut body: Value,
+        stream: bool,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Value, BackendError> {
+        if stream {
+            body["stream"] = json!(true);
+            // Without this, streamed replies do not report their usage.
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        let model = body["model"].as_str().map(String::from).unwrap_or_default();
+
+        let url = format!(
+            "{}/chat/completions",
+            self.config.base_url.trim_end_matches('/')
+        );
+        let body = body.to_string();
+        let response = self.client.send(
+            |client| {
+                let mut request = client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .body(body.clone());
+                if let Some(api_key) = &self.api_key {
+                    request = request.header("Authorization", format!("Bearer {}", api_key));
+                }
+                for (name, value) in &self.config.headers {
+                    request = request.header(name, value);
+                }
+                request
+            },
+            cancellation,
+            on_progress,
+        )?;
+
+        if stream {
+            let content =
+                read_event_stream(BufReader::new(response), &model, cancellation, on_progress)?;
+            return Ok(json!({ "role": "assistant", "content": content }));
+        }
+
+        let mut response_json: Value = response.json()?;
+        if let Some(usage) = parse_usage(&response_json["usage"], &model) {
+            on_progress(Progress::Usage(usage));
+        }
+        Ok(response_json["choices"][0]["message"].take())
+    }
+
+    /// Parses a text reply, which is JSON matching the schema with structured output, or YAML.
+    fn parse_reply(&self, text: String) -> Result<Vec<Action>, BackendError> {
+        if self.config.structured_output {
+            if let Ok(actions) = Action::from_structured_json(&text) {
+                return Ok(actions);
+            }
+        }
+
+        match Action::parse_all(&text) {
+            Ok(actions) => Ok(actions),
+            Err(e) => Err(BackendError::Parse {
+                output: text,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    /// Turns each tool call of the reply into an action, in the order they were made. A reply
+    /// without tool calls is parsed as text, since some models answer in text anyway.
+    fn parse_tool_calls(&self, message: &Value) -> Result<Vec<Action>, BackendError> {
+        let calls = match message["tool_calls"].as_array() {
+            Some(calls) if !calls.is_empty() => calls,
+            _ => return self.parse_reply(message_content(message)?),
+        };
+
+        calls
+            .iter()
+            .map(|call| {
+                let function = &call["function"];
+                Action::from_tool_call(
+                    function["name"].as_str().unwrap_or_default(),
+                    function["arguments"].as_str().unwrap_or_default(),
+                )
+                .map_err(|e| BackendError::Parse {
+                    output: message["tool_calls"].to_string(),
+                    error: format!("invalid call to {}: {}", function["name"], e),
+                })
+            })
+            .collect()
+    }
+}
+
+fn message_content(message: &Value) -> Result<String, BackendError> {
+    message["content"]
+        .as_str()
+        .map(String::from)
+        .ok_or(BackendError::MissingContent)
+}
+
+/// Reads the `usage` block of a chat completion or its final streamed chunk.
+fn parse_usage(usage: &Value, model: &str) -> Option<Usage> {
+    Some(Usage::new(
+        model,
+        usage["prompt_tokens"].as_u64()?,
+        usage["completion_tokens"].as_u64()?,
+    ))
+}
+
+/// Reads a server-sent event stream of chat completion chunks, accumulating the content deltas.
+/// Reading stops if the turn is cancelled.
+fn read_event_stream(
+    reader: impl BufRead,
+    model: &str,
+    cancellation: &Cancellation,
+    on_progress: &mut dyn FnMut(Progress),
+) -> Result<String, BackendError> {
+    let mut content = String::new();
+    for line in reader.lines() {
+        cancellation.check()?;
+        let line = line.map_err(|_| BackendError::MissingContent)?;
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            continue;
+        };
+        if let Some(delta) = chunk["choices"][0]["delta"]["content"].as_str() {
+            content += delta;
+            on_progress(Progress::Partial(content.clone()));
+        }
+        if let Some(usage) = parse_usage(&chunk["usage"], model) {
+            on_progress(Progress::Usage(usage));
+        }
+    }
+
+    if content.is_empty() {
+        return Err(BackendError::MissingContent);
+    }
+    Ok(content)
+}
+
+impl Backend for OpenAiBackend {
+    fn name(&self) -> &str {
+        "OpenAI"
+    }
+
+    fn action_model(&self) -> Option<&str> {
+        Some(self.config.models.get_turn().get_model())
+    }
+
+    /// Tools take precedence over structured output, as they do in `generate_action`.
+    fn response_format(&self) -> ResponseFormat {
+        if self.config.tools {
+            ResponseFormat::Tools
+        } else if self.config.structured_output {
+            ResponseFormat::Json
+        } else {
+            ResponseFormat::Yaml
+        }
+    }
+
+    fn generate_overview(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        let body = self.request_body(self.config.models.get_world(), prompt);
+        message_content(&self.send_request(body, false, prompt.get_cancellation(), on_progress)?)
+    }
+
+    fn generate_summary(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        let body = self.request_body(self.config.models.get_summary(), prompt);
+        message_content(&self.send_request(body, false, prompt.get_cancellation(), on_progress)?)
+    }
+
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        let mut body = self.request_body(self.config.models.get_turn(), prompt);
+        if self.config.tools {
+            body["tools"] = Action::tool_definitions();
+            body["tool_choice"] = json!("required");
+            let message = self.send_request(body, false, prompt.get_cancellation(), on_progress)?;
+            return self.parse_tool_calls(&message);
+        }
+        if self.config.structured_output {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "actions",
+                    "strict": true,
+                    "schema": Action::json_schema()
+                }
+            });
+        }
+        let message = self.send_request(
+            body,
+            self.config.stream,
+            prompt.get_cancellation(),
+            on_progress,
+        )?;
+        self.parse_reply(message_content(&message)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::game_loader::backend::{
+        stub_server::{StubResponse, StubServer},
+        ChatMessage,
+    };
+
+    use super::*;
+
+    #[test]
+    fn openai_backend_generates_action_from_local_server() {
+        let server = StubServer::start(vec![StubResponse::new(
+            200,
+            r#"{"choices": [{"message": {"role": "assistant", "content": "type: Information\nmessage: Hello."}}], "usage": {"prompt_tokens": 52, "completion_tokens": 9, "total_tokens": 61}}"#,
+        )]);
+        let config = OpenAiConfig {
+            base_url: format!("{}/v1/", server.url()),
+            api_key: Some(String::from("local-key")),
+            headers: BTreeMap::from([(String::from("X-Team"), String::from("adventure"))]),
+            models: ModelProfiles::new(
+                ModelProfile::new("local-model"),
+                serde_yaml::from_str("{ model: local-model, temperature: 0.5, max_tokens: 300, seed: 7, stop: [END] }").unwrap(),
+                ModelProfile::new("local-model"),
+            ),
+            ..OpenAiConfig::default()
+        };
+        let mut backend = OpenAiBackend::new(config, HttpConfig::default());
+        let prompt = Prompt::new(
+            String::from("You are a game."),
+            vec![ChatMessage::user(String::from("look"))],
+        );
+
+        let mut usages = Vec::new();
+        let action = backend
+            .generate_action(&prompt, &mut |progress| {
+                if let Progress::Usage(usage) = progress {
+                    usages.push(usage);
+                }
+            })
+            .unwrap();
+
+        let expected = vec![Action::Information {
+            message: String::from("Hello."),
+        }];
+        assert_eq!(action, expected);
+        assert_eq!(usages, vec![Usage::new("local-model", 52, 9)]);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/v1/chat/completions");
+        assert_eq!(request.header("Authorization"), Some("Bearer local-key"));
+        assert_eq!(request.header("X-Team"), Some("adventure"));
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["model"], "local-model");
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["max_tokens"], 300);
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["stop"], json!(["END"]));
+        assert!(body.get("top_p").is_none());
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "look");
+    }
+
+    #[test]
+    fn openai_backend_turns_parallel_tool_calls_into_actions() {
+        let server = StubServer::start(vec![StubResponse::new(
+            200,
+            r#"{"choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "AddToInventory", "arguments": "{\"item\": \"Brass Lamp\", \"message\": \"You take the lamp.\"}"}},
+                {"id": "call_2", "type": "function", "function": {"name": "NewScene", "arguments": "{\"name\": \"The Yard\", \"desc\": \"You walk north.\"}"}}
+            ]}}]}"#,
+        )]);
+        let config = OpenAiConfig {
+            base_url: String::from(server.url()),
+            tools: true,
+            stream: true,
+            ..OpenAiConfig::default()
+        };
+        let mut backend = OpenAiBackend::new(config, HttpConfig::default());
+        let prompt = Prompt::new(
+            String::from("You are a game."),
+            vec![ChatMessage::user(String::from(
+                "take the lamp and go north",
+            ))],
+        );
+
+        let actions = backend.generate_action(&prompt, &mut |_| {}).unwrap();
+
+        let expected = vec![
+            Action::AddToInventory {
+                item: String::from("Brass Lamp"),
+                message: String::from("You take the lamp."),
+                quantity: 1,
+                desc: String::new(),
+                tags: Vec::new(),
+            },
+            Action::NewScene {
+                name: String::from("The Yard"),
+                desc: String::from("You walk north."),
+            },
+        ];
+        assert_eq!(actions, expected);
+
+        let body: Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(body["tools"], Action::tool_definitions());
+        assert_eq!(body["tool_choice"], "required");
+        assert!(body.get("stream").is_none());
+    }
+
+    #[test]
+    fn openai_backend_response_format_follows_config() {
+        let format = |tools, structured_output| {
+            let config = OpenAiConfig {
+                tools,
+                structured_output,
+                ..OpenAiConfig::default()
+            };
+            OpenAiBackend::new(config, HttpConfig::default()).response_format()
+        };
+
+        assert_eq!(format(false, false), ResponseFormat::Yaml);
+        assert_eq!(format(false, true), ResponseFormat::Json);
+        assert_eq!(format(true, true), ResponseFormat::Tools);
+    }
+
+    #[test]
+    fn openai_config_deserialize_local_server() {
+        let yaml = r#"
+            base_url: http://localhost:8080/v1
+            headers:
+              X-Team: adventure
+            models:
+              turn:
+                model: llama-3-8b-instruct
+                temperature: 0.7
+                top_p: 0.9
+        "#;
+
+        let config: OpenAiConfig = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(config.base_url, "http://localhost:8080/v1");
+        assert_eq!(config.api_key, None);
+        assert_eq!(
+            config.headers.get("X-Team").map(String::as_str),
+            Some("adventure")
+        );
+        assert_eq!(config.models.get_world().get_model(), "gpt-4-1106-preview");
+        let turn = config.models.get_turn();
+        assert_eq!(turn.get_model(), "llama-3-8b-instruct");
+        assert_eq!(turn.get_temperature(), Some(0.7));
+        assert_eq!(turn.get_top_p(), Some(0.9));
+        assert_eq!(turn.get_max_tokens(), None);
+    }
+
+    #[test]
+    fn openai_read_event_stream() {
+        let stream = concat!(
+            ": keep-alive\n",
+            "\n",
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n",
+            "\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"type: Information\\n\"}}]}\n",
+            "\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"message: Hello\"}}]}\n",
+            "\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":120,\"completion_tokens\":8}}\n",
+            "\n",
+            "data: [DONE]\n",
+        );
+        let mut partials = Vec::new();
+        let mut usages = Vec::new();
+
+        let content = read_event_stream(
+            Cursor::new(stream),
+            "local-model",
+            &Cancellation::default(),
+            &mut |progress| match progress {
+                Progress::Partial(text) => partials.push(text),
+                Progress::Usage(usage) => usages.push(usage),
+                _ => {}
+            },
+        )
+        .unwrap();
+
+        assert_eq!(content, "type: Information\nmessage: Hello");
+        assert_eq!(
+            partials,
+            vec!["type: Information\n", "type: Information\nmessage: Hello"]
+        );
+        assert_eq!(usages, vec![Usage::new("local-model", 120, 8)]);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+/// The model and sampling parameters used for one kind of request. Parameters that are not set
+/// are left to the server's defaults.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ModelProfile {
+    model: String,
+    #[serde(default)]
+    temperature: Option<f32>,
+    #[serde(default)]
+    top_p: Option<f32>,
+    #[serde(default)]
+    max_tokens: Option<u32>,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    stop: Vec<String>,
+}
+
+impl ModelProfile {
+    pub fn new(model: &str) -> Self {
+        ModelProfile {
+            model: String::from(model),
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            seed: None,
+            stop: Vec::new(),
+        }
+    }
+
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn get_temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    pub fn get_top_p(&self) -> Option<f32> {
+        self.top_p
+    }
+
+    pub fn get_max_tokens(&self) -> Option<u32> {
+        self.max_tokens
+    }
+
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn get_stop(&self) -> &[String] {
+        &self.stop
+    }
+}
+
+/// A model profile for each role the backend is asked to play.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ModelProfiles {
+    /// Generates the overview of a new game.
+    world: ModelProfile,
+    /// Resolves each turn into an action.
+    turn: ModelProfile,
+    /// Summarizes the story so far.
+    summary: ModelProfile,
+}
+
+impl ModelProfiles {
+    pub fn new(world: ModelProfile, turn: ModelProfile, summary: ModelProfile) -> Self {
+        ModelProfiles {
+            world,
+            turn,
+            summary,
+        }
+    }
+
+    pub fn get_world(&self) -> &ModelProfile {
+        &self.world
+    }
+
+    pub fn get_turn(&self) -> &ModelProfile {
+        &self.turn
+    }
+
+    pub fn get_summary(&self) -> &ModelProfile {
+        &self.summary
+    }
+}
+
+impl Default for ModelProfiles {
+    fn default() -> Self {
+        ModelProfiles::new(
+            ModelProfile::new("gpt-4-1106-preview"),
+            ModelProfile::new("gpt-3.5-turbo"),
+            ModelProfile::new("gpt-3.5-turbo"),
+        )
+    }
+}
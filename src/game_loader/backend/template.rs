@@ -0,0 +1,280 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::action::Action;
+
+use super::{Backend, BackendError, HttpClient, HttpConfig, Progress, Prompt};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TemplateConfig {
+    /// The URL requests are posted to.
+    url: String,
+    /// Headers sent with every request, such as credentials.
+    headers: BTreeMap<String, String>,
+    /// The request body. `{{system}}`, `{{context}}`, `{{messages}}` and `{{task}}` are replaced
+    /// with JSON values, so they must not be quoted in the template.
+    body: String,
+    /// A JSON pointer to the text of the reply in the response, such as `/output/text`.
+    reply_pointer: String,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        TemplateConfig {
+            url: String::new(),
+            headers: BTreeMap::new(),
+            body: String::from(r#"{"system": {{system}}, "prompt": {{context}}}"#),
+            reply_pointer: String::from("/text"),
+        }
+    }
+}
+
+/// A backend for gateways with their own request format, described entirely by its config: the
+/// request body is rendered from a template, and the reply is found with a JSON pointer.
+pub struct TemplateBackend {
+    config: TemplateConfig,
+    client: HttpClient,
+}
+
+impl TemplateBackend {
+    pub fn new(config: TemplateConfig, http: HttpConfig) -> Self {
+        TemplateBackend {
+            config,
+            client: HttpClient::new(http),
+        }
+    }
+
+    /// Fills in the slots of the body template. `{{context}}` is the conversation as plain text,
+    /// for gateways that take a single prompt, and `{{messages}}` the same as a list of role and
+    /// content objects. `{{task}}` is `overview`, `summary` or `action`, for gateways that route
+    /// each kind of request to a different model. The slots are filled in a single pass, so slot
+    /// names in the prompt itself, such as in the player's input, are left alone.
+    fn render_body(&self, task: &str, prompt: &Prompt) -> String {
+        let messages = prompt.get_messages();
+        let context = match &messages[..] {
+            [message] => json!(message)["content"].clone(),
+            messages => json!(messages
+                .iter()
+                .map(|message| {
+                    let message = json!(message);
+                    format!(
+                        "{}: {}",
+                        message["role"].as_str().unwrap_or_default(),
+                        message["content"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n")),
+        };
+
+        static SLOT: OnceLock<Regex> = OnceLock::new();
+        let slot =
+            SLOT.get_or_init(|| Regex::new(r"\{\{(system|context|messages|task)\}\}").unwrap());
+        slot.replace_all(&self.config.body, |captures: &Captures| {
+            match &captures[1] {
+                "system" => json!(prompt.get_system()),
+                "context" => context.clone(),
+                "messages" => json!(messages),
+                _ => json!(task),
+            }
+            .to_string()
+        })
+        .into_owned()
+    }
+
+    fn send_request(
+        &self,
+        task: &str,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        let body = self.render_body(task, prompt);
+        let response = self.client.send(
+            |client| {
+                let mut request = client
+                    .post(&self.config.url)
+                    .header("Content-Type", "application/json")
+                    .body(body.clone());
+                for (name, value) in &self.config.headers {
+                    request = request.header(name, value);
+                }
+                request
+            },
+            prompt.get_cancellation(),
+            on_progress,
+        )?;
+
+        let response_json: Value = response.json()?;
+        response_json
+            .pointer(&self.config.reply_pointer)
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or(BackendError::MissingContent)
+    }
+}
+
+impl Backend for TemplateBackend {
+    fn name(&self) -> &str {
+        "Template"
+    }
+
+    fn generate_overview(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        self.send_request("overview", prompt, on_progress)
+    }
+
+    fn generate_summary(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<String, BackendError> {
+        self.send_request("summary", prompt, on_progress)
+    }
+
+    fn generate_action(
+        &mut self,
+        prompt: &Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        let text = self.send_request("action", prompt, on_progress)?;
+        match Action::parse_all(&text) {
+            Ok(actions) => Ok(actions),
+            Err(e) => Err(BackendError::Parse {
+                output: text,
+                error: e.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game_loader::backend::{
+        stub_server::{StubResponse, StubServer},
+        ChatMessage,
+    };
+
+    use super::*;
+
+    #[test]
+    fn template_backend_renders_body_and_extracts_reply() {
+        let server = StubServer::start(vec![StubResponse::new(
+            200,
+            r#"{"result": {"choices": [{"output": "type: Information\nmessage: Hello."}]}}"#,
+        )]);
+        let config: TemplateConfig = serde_yaml::from_str(&format!(
+            r#"
+                url: {}/generate
+                headers:
+                  X-Gateway-Key: secret
+                body: '{{"instructions": {{{{system}}}}, "input": {{{{context}}}}, "route": {{{{task}}}}}}'
+                reply_pointer: /result/choices/0/output
+            "#,
+            server.url()
+        ))
+        .unwrap();
+        let mut backend = TemplateBackend::new(config, HttpConfig::default());
+        let prompt = Prompt::new(
+            String::from("You are a \"game\"."),
+            vec![ChatMessage::user(String::from("look\naround"))],
+        );
+
+        let actions = backend.generate_action(&prompt, &mut |_| {}).unwrap();
+
+        let expected = vec![Action::Information {
+            message: String::from("Hello."),
+        }];
+        assert_eq!(actions, expected);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/generate");
+        assert_eq!(request.header("X-Gateway-Key"), Some("secret"));
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "instructions": "You are a \"game\".",
+                "input": "look\naround",
+                "route": "action"
+            })
+        );
+    }
+
+    #[test]
+    fn template_backend_joins_conversation_into_context() {
+        let backend = TemplateBackend::new(
+            TemplateConfig {
+                body: String::from(r#"{"prompt": {{context}}, "messages": {{messages}}}"#),
+                ..TemplateConfig::default()
+            },
+            HttpConfig::default(),
+        );
+        let prompt = Prompt::new(
+            String::new(),
+            vec![
+                ChatMessage::user(String::from("look")),
+                ChatMessage::assistant(String::from("type: Dance")),
+                ChatMessage::user(String::from("Please try again.")),
+            ],
+        );
+
+        let body: Value = serde_json::from_str(&backend.render_body("action", &prompt)).unwrap();
+
+        assert_eq!(
+            body["prompt"],
+            "user: look\n\nassistant: type: Dance\n\nuser: Please try again."
+        );
+        assert_eq!(body["messages"][1]["role"], "assistant");
+    }
+
+    #[test]
+    fn template_backend_reports_missing_reply() {
+        let server = StubServer::start(vec![StubResponse::new(200, r#"{"error": "no"}"#)]);
+        let config = TemplateConfig {
+            url: String::from(server.url()),
+            ..TemplateConfig::default()
+        };
+        let mut backend = TemplateBackend::new(config, HttpConfig::default());
+        let prompt = Prompt::new(String::new(), vec![ChatMessage::user(String::from("look"))]);
+
+        let result = backend.generate_overview(&prompt, &mut |_| {});
+
+        assert!(matches!(result, Err(BackendError::MissingContent)));
+    }
+
+    #[test]
+    fn template_backend_leaves_slot_names_in_prompt_alone() {
+        let backend = TemplateBackend::new(
+            TemplateConfig {
+                body: String::from(
+                    r#"{"system": {{system}}, "prompt": {{context}}, "messages": {{messages}}, "route": {{task}}}"#,
+                ),
+                ..TemplateConfig::default()
+            },
+            HttpConfig::default(),
+        );
+        let prompt = Prompt::new(
+            String::from("Ignore {{messages}}."),
+            vec![ChatMessage::user(String::from(
+                "say {{task}} and {{context}}",
+            ))],
+        );
+
+        let body: Value = serde_json::from_str(&backend.render_body("action", &prompt)).unwrap();
+
+        assert_eq!(body["system"], "Ignore {{messages}}.");
+        assert_eq!(body["prompt"], "say {{task}} and {{context}}");
+        assert_eq!(
+            body["messages"][0]["content"],
+            "say {{task}} and {{context}}"
+        );
+        assert_eq!(body["route"], "action");
+    }
+}
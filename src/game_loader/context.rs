@@ -0,0 +1,687 @@
+// The context builder decides what the model gets to see each turn, fitting it into a token budget
+
+use std::{collections::BTreeMap, collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{self, one_or_many, Action},
+    model::{
+        game_state::GameState,
+        item::Item,
+        stats::Stats,
+        world::{Scene, World},
+    },
+};
+
+use super::{backend::ChatMessage, ActionHistoryItem, START_INPUT};
+
+/// Entries are only shortened to fit the budget if at least this many characters of their
+/// narrative can be kept. Otherwise they are dropped.
+const MIN_SHORTENED_CHARS: usize = 80;
+
+/// Descriptions of scenes on the map other than the current one are cut down to this many
+/// characters. The game restores the whole description when the player returns.
+const MAP_DESC_CHARS: usize = 200;
+
+/// How many of the most recent events of each scene are shown on the map.
+const MAP_EVENTS: usize = 3;
+
+/// The share of the budget left after the other fixed parts that the map may use, in percent.
+/// Past it, the other scenes on the map are cut down until it fits, so that the map of a long
+/// game does not crowd out the history.
+const MAP_BUDGET_PERCENT: usize = 50;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContextConfig {
+    /// Token budget for the context sent each turn, for models without an entry in `models`.
+    budget: usize,
+    /// Token budgets for specific models, keyed by model name.
+    models: BTreeMap<String, usize>,
+    /// How the context is laid out in the messages sent to the backend.
+    mode: ContextMode,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextMode {
+    /// The whole context as YAML, in a single user message.
+    #[default]
+    Yaml,
+    /// The overview first, then each turn in the history as a user message with the player's
+    /// input and an assistant message with the action, and the inventory and input last. The
+    /// messages before the newest turn stay the same from one turn to the next, so providers can
+    /// reuse their cached prefix. Over budget, the history is dropped in batches to keep it so.
+    Chat,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        ContextConfig {
+            budget: 3000,
+            models: BTreeMap::new(),
+            mode: ContextMode::default(),
+        }
+    }
+}
+
+impl ContextConfig {
+    pub fn budget_for(&self, model: Option<&str>) -> usize {
+        model
+            .and_then(|model| self.models.get(model))
+            .copied()
+            .unwrap_or(self.budget)
+    }
+
+    pub fn get_mode(&self) -> ContextMode {
+        self.mode
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Context {
+    overview: String,
+    /// A summary of the story before the oldest entry in `history`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    summary: String,
+    /// The scenes the player has visited, and the ID of the one they are in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    map: Vec<MapScene>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(default)]
+    stats: Stats,
+    inventory: Vec<Item>,
+    history: Vec<ContextTurn>,
+    input: String,
+}
+
+/// A known scene as the model sees it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct MapScene {
+    id: String,
+    name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    desc: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exits: Vec<String>,
+    /// The most recent things that happened in the scene.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    events: Vec<String>,
+    /// The items lying in the scene, which the player can pick up.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    items: Vec<Item>,
+}
+
+impl MapScene {
+    fn new(scene: &Scene, is_current: bool) -> Self {
+        let desc = match is_current {
+            true => String::from(scene.get_desc()),
+            false => action::shorten(scene.get_desc(), MAP_DESC_CHARS),
+        };
+        let events = scene.get_events();
+        MapScene {
+            id: String::from(scene.get_id()),
+            name: String::from(scene.get_name()),
+            desc,
+            exits: scene.get_exits().to_vec(),
+            events: events[events.len().saturating_sub(MAP_EVENTS)..].to_vec(),
+            items: scene.get_items().to_vec(),
+        }
+    }
+
+    fn drop_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// Cuts the scene down to its ID and name.
+    fn make_brief(&mut self) {
+        self.desc.clear();
+        self.exits.clear();
+        self.events.clear();
+        self.items.clear();
+    }
+}
+
+fn estimate_map_tokens(map: &[MapScene]) -> usize {
+    estimate_tokens(&serde_yaml::to_string(map).unwrap())
+}
+
+/// The map of the world, fitted into `budget` tokens. The current scene is always kept in full.
+/// Over budget, the other scenes first lose their events, then everything but their ID and name,
+/// and last, scenes that are not an exit of the current one are left out.
+fn fit_map(world: &World, budget: usize) -> Vec<MapScene> {
+    let current = world.get_current();
+    let location = current.map(Scene::get_id);
+    let mut map: Vec<MapScene> = world
+        .get_scenes()
+        .map(|scene| MapScene::new(scene, Some(scene.get_id()) == location))
+        .collect();
+
+    for reduce in [MapScene::drop_events, MapScene::make_brief] {
+        if estimate_map_tokens(&map) <= budget {
+            return map;
+        }
+        map.iter_mut()
+            .filter(|scene| Some(scene.id.as_str()) != location)
+            .for_each(reduce);
+    }
+
+    if estimate_map_tokens(&map) > budget {
+        let exits = current.map_or(&[][..], Scene::get_exits);
+        map.retain(|scene| Some(scene.id.as_str()) == location || exits.contains(&scene.id));
+    }
+    map
+}
+
+/// The start of the first message in chat mode.
+#[derive(Serialize)]
+struct ChatPreamble<'a> {
+    overview: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    summary: &'a str,
+}
+
+/// The last message in chat mode. The map changes from turn to turn, so it is sent here rather
+/// than in the preamble.
+#[derive(Serialize)]
+struct ChatInput<'a> {
+    #[serde(skip_serializing_if = "<[MapScene]>::is_empty")]
+    map: &'a [MapScene],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<&'a str>,
+    stats: &'a Stats,
+    inventory: &'a [Item],
+    input: &'a str,
+}
+
+impl Context {
+    pub fn to_messages(&self, mode: ContextMode) -> Vec<ChatMessage> {
+        match mode {
+            ContextMode::Yaml => vec![ChatMessage::user(serde_yaml::to_string(self).unwrap())],
+            ContextMode::Chat => self.to_chat_messages(),
+        }
+    }
+
+    /// User and assistant messages alternate, so the preamble shares the first user message with
+    /// the oldest turn, or with the input when there is no history.
+    fn to_chat_messages(&self) -> Vec<ChatMessage> {
+        let preamble = ChatPreamble {
+            overview: &self.overview,
+            summary: &self.summary,
+        };
+        let mut user = vec![serde_yaml::to_string(&preamble).unwrap()];
+        let mut messages = Vec::new();
+        for turn in &self.history {
+            user.push(turn.input.clone().unwrap_or(String::from(START_INPUT)));
+            messages.push(ChatMessage::user(user.join("\n")));
+            let response =
+                one_or_many::serialize(&turn.response, serde_yaml::value::Serializer).unwrap();
+            messages.push(ChatMessage::assistant(
+                serde_yaml::to_string(&response).unwrap(),
+            ));
+            user.clear();
+        }
+
+        let input = ChatInput {
+            map: &self.map,
+            location: self.location.as_deref(),
+            stats: &self.stats,
+            inventory: &self.inventory,
+            input: &self.input,
+        };
+        user.push(serde_yaml::to_string(&input).unwrap());
+        messages.push(ChatMessage::user(user.join("\n")));
+        messages
+    }
+}
+
+/// A history entry as the model sees it, without the bookkeeping it has no use for.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ContextTurn {
+    turn: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    #[serde(with = "one_or_many")]
+    response: Vec<Action>,
+}
+
+impl ContextTurn {
+    pub fn shortened(&self, max_chars: usize) -> Self {
+        ContextTurn {
+            response: self
+                .response
+                .iter()
+                .map(|action| action.shortened(max_chars))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl From<&ActionHistoryItem> for ContextTurn {
+    fn from(item: &ActionHistoryItem) -> Self {
+        ContextTurn {
+            turn: item.get_turn(),
+            input: item.get_input().map(String::from),
+            response: item.get_response().to_vec(),
+        }
+    }
+}
+
+/// How many tokens each part of a context is estimated to use.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ContextReport {
+    budget: usize,
+    overview: usize,
+    summary: usize,
+    map: usize,
+    stats: usize,
+    inventory: usize,
+    history: usize,
+    input: usize,
+    included: usize,
+    shortened: usize,
+    dropped: usize,
+}
+
+impl ContextReport {
+    pub fn total(&self) -> usize {
+        self.overview
+            + self.summary
+            + self.map
+            + self.stats
+            + self.inventory
+            + self.history
+            + self.input
+    }
+
+    /// How many of the entries given to the builder did not fit, shortened or otherwise.
+    pub fn get_dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl fmt::Display for ContextReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} tokens - overview {}, summary {}, map {}, stats {}, inventory {}, history {} ({} entries, {} shortened, {} dropped), input {}",
+            self.total(),
+            self.budget,
+            self.overview,
+            self.summary,
+            self.map,
+            self.stats,
+            self.inventory,
+            self.history,
+            self.included,
+            self.shortened,
+            self.dropped,
+            self.input
+        )
+    }
+}
+
+/// A rough token estimate of about four characters per token, which is close enough for English
+/// prose with the tokenizers of most models.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+fn estimate_item_tokens(item: &ContextTurn) -> usize {
+    estimate_tokens(&serde_yaml::to_string(item).unwrap())
+}
+
+pub struct ContextBuilder {
+    budget: usize,
+    /// How many of the oldest history entries are dropped at a time.
+    batch: usize,
+}
+
+impl ContextBuilder {
+    pub fn new(budget: usize) -> Self {
+        ContextBuilder { budget, batch: 1 }
+    }
+
+    /// Drops the oldest history entries in batches of `batch`, and never shortens them, so the
+    /// start of the history only moves once every `batch` turns. Until it does, each context
+    /// begins the same way as the last, and providers can reuse their cached prefix.
+    pub fn with_batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// Builds a context for the player's state that fits the budget. The overview, summary, map,
+    /// stats, inventory and input are always included. History fills the rest of the budget from
+    /// the newest entry back, so the oldest entries are the first to be shortened or dropped.
+    pub fn build(
+        &self,
+        overview: &str,
+        summary: &str,
+        state: &GameState,
+        history: &[ActionHistoryItem],
+        input: &str,
+    ) -> (Context, ContextReport) {
+        let mut report = ContextReport {
+            budget: self.budget,
+            overview: estimate_tokens(overview),
+            summary: estimate_tokens(summary),
+            stats: estimate_tokens(&serde_yaml::to_string(state.get_stats()).unwrap()),
+            inventory: estimate_tokens(&serde_yaml::to_string(state.get_inventory()).unwrap()),
+            input: estimate_tokens(input),
+            ..ContextReport::default()
+        };
+        let world = state.get_world();
+        let location = world.get_current().map(Scene::get_id);
+        let map_budget = self.budget.saturating_sub(report.total()) * MAP_BUDGET_PERCENT / 100;
+        let map = fit_map(world, map_budget);
+        report.map = estimate_map_tokens(&map);
+
+        let mut remaining = self.budget.saturating_sub(report.total());
+        let mut included = VecDeque::new();
+        for item in history.iter().rev() {
+            let item = ContextTurn::from(item);
+            let tokens = estimate_item_tokens(&item);
+            if tokens <= remaining {
+                included.push_front(item);
+                remaining -= tokens;
+                continue;
+            }
+
+            // Keep a shortened version of the entry that does not fit, if enough of it is left. A
+            // shortened entry changes from turn to turn, so batched history never has one.
+            if self.batch > 1 {
+                break;
+            }
+            let overhead = estimate_item_tokens(&item.shortened(0));
+            let max_chars = remaining.saturating_sub(overhead) * 4;
+            if max_chars >= MIN_SHORTENED_CHARS {
+                let shortened = item.shortened(max_chars);
+                included.push_front(shortened);
+                report.shortened += 1;
+            }
+            break;
+        }
+
+        let dropped = history.len() - included.len();
+        if dropped > 0 {
+            let batched = dropped.next_multiple_of(self.batch).min(history.len());
+            included.drain(..batched - dropped);
+        }
+
+        report.history = included.iter().map(estimate_item_tokens).sum();
+        report.included = included.len();
+        report.dropped = history.len() - included.len();
+
+        let context = Context {
+            overview: String::from(overview),
+            summary: String::from(summary),
+            map,
+            location: location.map(String::from),
+            stats: state.get_stats().clone(),
+            inventory: state.get_inventory().clone(),
+            history: included.into(),
+            input: String::from(input),
+        };
+        (context, report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history(messages: &[&str]) -> Vec<ActionHistoryItem> {
+        messages
+            .iter()
+            .enumerate()
+            .map(|(turn, message)| {
+                ActionHistoryItem::new(
+                    turn + 1,
+                    Some(String::from("look")),
+                    vec![Action::Information {
+                        message: message.to_string(),
+                    }],
+                )
+            })
+            .collect()
+    }
+
+    fn messages(context: &Context) -> Vec<String> {
+        context
+            .history
+            .iter()
+            .map(|item| match &item.response[..] {
+                [Action::Information { message }] => message.clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn context_includes_everything_within_budget() {
+        let history = history(&["first", "second", "third"]);
+
+        let (context, report) = ContextBuilder::new(3000).build(
+            "An overview.",
+            "",
+            &GameState::new(),
+            &history,
+            "look",
+        );
+
+        assert_eq!(messages(&context), vec!["first", "second", "third"]);
+        assert_eq!(report.included, 3);
+        assert_eq!(report.dropped, 0);
+        assert!(report.history > 0);
+        assert!(report.total() <= 3000);
+    }
+
+    #[test]
+    fn context_drops_and_shortens_oldest_entries_first() {
+        let long = "word ".repeat(200);
+        let history = history(&[&long, &long, "recent", "latest"]);
+        let budget = estimate_tokens(&long) + 40;
+
+        let (context, report) =
+            ContextBuilder::new(budget).build("", "", &GameState::new(), &history, "look");
+
+        let messages = messages(&context);
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].ends_with("..."));
+        assert!(messages[0].len() < long.len());
+        assert_eq!(
+            messages[1..],
+            [String::from("recent"), String::from("latest")]
+        );
+        assert_eq!(report.shortened, 1);
+        assert_eq!(report.dropped, 1);
+        assert!(report.total() <= budget);
+    }
+
+    #[test]
+    fn context_keeps_fixed_parts_over_budget() {
+        let history = history(&["first"]);
+
+        let (context, report) = ContextBuilder::new(2).build(
+            "A long overview.",
+            "",
+            &GameState::new(),
+            &history,
+            "look",
+        );
+
+        assert_eq!(context.overview, "A long overview.");
+        assert!(context.history.is_empty());
+        assert_eq!(report.history, 0);
+        assert_eq!(report.dropped, 1);
+    }
+
+    #[test]
+    fn context_chat_messages_alternate() {
+        let history = history(&["first"]);
+
+        let (context, _) = ContextBuilder::new(3000).build(
+            "An overview.",
+            "",
+            &GameState::new(),
+            &history,
+            "open the door",
+        );
+
+        let messages = context.to_messages(ContextMode::Chat);
+        let expected = vec![
+            ChatMessage::user(String::from("overview: An overview.\n\nlook")),
+            ChatMessage::assistant(String::from("type: Information\nmessage: first\n")),
+            ChatMessage::user(String::from(
+                "stats:\n  health: 100\n  stamina: 100\n  gold: 0\ninventory: []\ninput: open the door\n",
+            )),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn context_chat_messages_keep_a_stable_prefix() {
+        let mut history = history(&["first", "second"]);
+        let mut state = GameState::new();
+        state.add_to_inventory(Item::new("Flashlight"));
+        let builder = ContextBuilder::new(3000);
+
+        let (context, _) = builder.build("An overview.", "", &state, &history, "look");
+        let before = context.to_messages(ContextMode::Chat);
+        history.push(ActionHistoryItem::new(
+            3,
+            Some(String::from("look")),
+            vec![Action::Information {
+                message: String::from("third"),
+            }],
+        ));
+        let (context, _) = builder.build("An overview.", "", &state, &history, "look");
+        let after = context.to_messages(ContextMode::Chat);
+
+        assert_eq!(after.len(), before.len() + 2);
+        assert_eq!(before[..before.len() - 1], after[..before.len() - 1]);
+    }
+
+    #[test]
+    fn context_chat_messages_keep_a_stable_prefix_over_budget() {
+        let long = "word ".repeat(40);
+        let state = GameState::new();
+        let (_, fixed) = ContextBuilder::new(3000).build("An overview.", "", &state, &[], "look");
+        let entry = estimate_item_tokens(&ContextTurn::from(&history(&[&long])[0]));
+        let builder = ContextBuilder::new(fixed.total() + entry * 5).with_batch(4);
+
+        let mut contexts = Vec::new();
+        for turns in 6..=8 {
+            let history = history(&vec![long.as_str(); turns]);
+            let (context, report) = builder.build("An overview.", "", &state, &history, "look");
+            assert_eq!(report.dropped, 4);
+            assert_eq!(report.shortened, 0);
+            assert!(report.total() <= fixed.total() + entry * 5);
+            contexts.push(context.to_messages(ContextMode::Chat));
+        }
+
+        for pair in contexts.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            assert_eq!(after.len(), before.len() + 2);
+            assert_eq!(before[..before.len() - 1], after[..before.len() - 1]);
+        }
+
+        let history = history(&[long.as_str(); 10]);
+        let (_, report) = builder.build("An overview.", "", &state, &history, "look");
+        assert_eq!(report.dropped, 8);
+    }
+
+    #[test]
+    fn context_includes_map_of_known_scenes() {
+        let long = "word ".repeat(100);
+        let mut state = GameState::new();
+        state.new_scene(String::from("The Old House"), long.clone());
+        for event in ["one", "two", "three", "four"] {
+            state.record_event(event);
+        }
+        state.add_to_scene(Item::new("Brass Lamp"));
+        state.new_scene(String::from("The Yard"), long.clone());
+
+        let (context, report) =
+            ContextBuilder::new(3000).build("", "", &state, &[], "go back inside");
+
+        assert_eq!(context.location.as_deref(), Some("the-yard"));
+        let house = &context.map[0];
+        assert_eq!(house.id, "the-old-house");
+        assert_eq!(house.exits, vec![String::from("the-yard")]);
+        assert_eq!(house.events, vec!["two", "three", "four"]);
+        assert_eq!(house.items, vec![Item::new("Brass Lamp")]);
+        assert!(context.map[1].items.is_empty());
+        assert!(house.desc.ends_with("..."));
+        assert!(house.desc.len() <= MAP_DESC_CHARS);
+        assert_eq!(context.map[1].desc, long);
+        assert!(report.map > 0);
+
+        let messages = context.to_messages(ContextMode::Chat);
+        let last = serde_yaml::to_value(messages.last().unwrap()).unwrap();
+        let input: serde_yaml::Value =
+            serde_yaml::from_str(last["content"].as_str().unwrap()).unwrap();
+        assert_eq!(input["location"], "the-yard");
+        assert_eq!(input["map"][1]["name"], "The Yard");
+    }
+
+    #[test]
+    fn context_fits_large_map_into_budget() {
+        let long = "word ".repeat(40);
+        let mut state = GameState::new();
+        for scene in 0..60 {
+            state.new_scene(format!("Scene {}", scene), long.clone());
+            state.record_event(&long);
+            state.add_to_scene(Item::new("Rock"));
+        }
+        let history = history(&["first", "second", "third"]);
+
+        let (context, report) = ContextBuilder::new(1000).build("", "", &state, &history, "look");
+
+        assert!(report.map <= 500);
+        assert_eq!(report.included, 3);
+        let current = context
+            .map
+            .iter()
+            .find(|scene| scene.id == "scene-59")
+            .unwrap();
+        assert_eq!(current.desc, long);
+        assert_eq!(current.events, vec![long.clone()]);
+        assert_eq!(current.exits, vec![String::from("scene-58")]);
+        let exit = context
+            .map
+            .iter()
+            .find(|scene| scene.id == "scene-58")
+            .unwrap();
+        assert!(exit.desc.is_empty() && exit.events.is_empty() && exit.items.is_empty());
+
+        let (context, _) = ContextBuilder::new(3000).build("", "", &state, &[], "look");
+        assert_eq!(context.map.len(), 60);
+        assert!(context
+            .map
+            .iter()
+            .filter(|scene| scene.id != "scene-59")
+            .all(|scene| scene.events.is_empty()));
+    }
+
+    #[test]
+    fn context_config_budget_per_model() {
+        let config: ContextConfig = serde_yaml::from_str(
+            r#"
+                budget: 2000
+                mode: chat
+                models:
+                  gpt-4-1106-preview: 100000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.budget_for(Some("gpt-4-1106-preview")), 100000);
+        assert_eq!(config.budget_for(Some("gpt-3.5-turbo")), 2000);
+        assert_eq!(config.budget_for(None), 2000);
+        assert_eq!(config.get_mode(), ContextMode::Chat);
+    }
+}
@@ -0,0 +1,57 @@
+// The memory keeps a running summary of the history the model can no longer see, so that plot
+// threads and characters are not forgotten once they fall out of the context
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    backend::{ChatMessage, Prompt},
+    context::ContextTurn,
+    ActionHistoryItem,
+};
+
+/// How many entries must have fallen out of the context before they are summarized, so that the
+/// backend is not asked for a new summary every turn.
+pub const SUMMARY_BATCH: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Memory {
+    summary: String,
+    /// How many of the oldest history entries are covered by the summary.
+    summarized: usize,
+}
+
+#[derive(Serialize)]
+struct SummaryRequest<'a> {
+    story_so_far: &'a str,
+    new_events: Vec<ContextTurn>,
+}
+
+impl Memory {
+    pub fn get_summary(&self) -> &str {
+        &self.summary
+    }
+
+    /// The history entries that are not covered by the summary yet.
+    pub fn unsummarized<'a>(&self, history: &'a [ActionHistoryItem]) -> &'a [ActionHistoryItem] {
+        &history[self.summarized.min(history.len())..]
+    }
+
+    /// Builds the prompt that asks the backend to fold the evicted entries into the summary. The
+    /// entries are shown the way the context shows them, without their bookkeeping.
+    pub fn summary_prompt(&self, system: &str, evicted: &[ActionHistoryItem]) -> Prompt {
+        let request = SummaryRequest {
+            story_so_far: &self.summary,
+            new_events: evicted.iter().map(ContextTurn::from).collect(),
+        };
+        Prompt::new(
+            String::from(system),
+            vec![ChatMessage::user(serde_yaml::to_string(&request).unwrap())],
+        )
+    }
+
+    /// Replaces the summary with one that also covers the next `count` history entries.
+    pub fn update(&mut self, summary: String, count: usize) {
+        self.summary = summary;
+        self.summarized += count;
+    }
+}
@@ -1,212 +1,725 @@
 // The game loader is responsible for loading responses to each user input and logically determine what actions should result
 
-use std::fs::{self, File, OpenOptions};
+use std::fs::{self, OpenOptions};
 use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
 
-use crate::{action::Action, model::game_state::GameState};
+use crate::{
+    action::{one_or_many, Action},
+    config::Config,
+    model::game_state::GameState,
+};
+
+use self::backend::{
+    Backend, BackendError, Cancellation, ChatMessage, FallbackBackend, Progress, Prompt,
+};
+use self::context::{ContextBuilder, ContextConfig, ContextMode};
+use self::memory::{Memory, SUMMARY_BATCH};
+use self::prompts::{PromptConfig, Prompts, DEFAULT_PACK};
+use self::usage::{UsageReport, UsageTracker};
+
+pub mod backend;
+pub mod context;
+mod memory;
+pub mod prompts;
+pub mod usage;
+
+/// The input the model is given for the opening turn, which the player did not type.
+const START_INPUT: &str = "start game";
+
+/// How many times the model is asked to correct an action it returned that could not be parsed.
+const MAX_REPAIR_ATTEMPTS: usize = 2;
 
 pub struct GameLoader {
-    api_key: String,
+    backend: Box<dyn Backend>,
     overview: String,
     action_history: Vec<ActionHistoryItem>,
-    system_prompt: String,
-    log: File,
+    /// The last turn played by `process_input`, added to the history once it is committed.
+    pending_turn: Option<ActionHistoryItem>,
+    memory: Memory,
+    /// How many unsummarized history entries did not fit into the last context.
+    evicted: usize,
+    prompt_config: PromptConfig,
+    /// The prompt pack of the current game.
+    pack: String,
+    prompts: Prompts,
+    context_config: ContextConfig,
+    usage: UsageTracker,
+    save_path: String,
+    log: Box<dyn Write + Send>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-pub struct Context {
+/// Everything needed to continue a game later.
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    #[serde(default = "default_pack")]
+    pack: String,
     overview: String,
-    inventory: Vec<String>,
+    memory: Memory,
     history: Vec<ActionHistoryItem>,
-    input: String,
+    state: GameState,
 }
 
-impl Context {
-    pub fn new(
-        overview: String,
-        inventory: Vec<String>,
-        history: Vec<ActionHistoryItem>,
-        input: String,
-    ) -> Self {
-        Context {
-            overview,
-            inventory,
-            history,
-            input,
-        }
-    }
+fn default_pack() -> String {
+    String::from(DEFAULT_PACK)
 }
 
+/// A completed turn: what the player typed and the actions it led to.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ActionHistoryItem {
-    input: String,
-    response: Action,
+    /// Turn 0 is the opening scene. Each input from the player is a turn after it.
+    turn: usize,
+    /// What the player typed, or `None` for the opening scene.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    #[serde(with = "one_or_many")]
+    response: Vec<Action>,
+    /// When the input was played, in seconds since the Unix epoch.
+    timestamp: u64,
 }
 
 impl ActionHistoryItem {
-    pub fn new(input: String, response: Action) -> Self {
-        ActionHistoryItem { input, response }
+    pub fn new(turn: usize, input: Option<String>, response: Vec<Action>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        ActionHistoryItem {
+            turn,
+            input,
+            response,
+            timestamp,
+        }
+    }
+
+    pub fn get_turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn get_input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn get_response(&self) -> &[Action] {
+        &self.response
     }
 }
 
 impl GameLoader {
-    pub fn new() -> Self {
-        let api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set.");
-        let system_prompt = fs::read_to_string("prompt.txt").expect("Failed to read prompt.txt");
+    pub fn new(config: &Config) -> Self {
+        // Fallbacks are only chained in when configured, so logs name the backend directly.
+        let http = config.get_http();
+        let mut backends = vec![config.get_backend().build(http)];
+        backends.extend(
+            config
+                .get_fallbacks()
+                .iter()
+                .map(|fallback| fallback.build(http)),
+        );
+        let backend: Box<dyn Backend> = if backends.len() == 1 {
+            backends.remove(0)
+        } else {
+            Box::new(FallbackBackend::new(backends))
+        };
         let log = OpenOptions::new()
-            .write(true)
+            .create(true)
             .append(true)
             .open("log.txt")
             .expect("Failed to open log.txt");
 
+        GameLoader::with_log(config, backend, Box::new(log))
+    }
+
+    pub fn with_log(
+        config: &Config,
+        backend: Box<dyn Backend>,
+        log: Box<dyn Write + Send>,
+    ) -> Self {
+        let prompt_config = config.get_prompts().clone();
+        let prompts = prompt_config.build(DEFAULT_PACK, backend.response_format());
+
         GameLoader {
-            api_key,
+            backend,
             overview: String::new(),
             action_history: Vec::new(),
-            system_prompt,
+            pending_turn: None,
+            memory: Memory::default(),
+            evicted: 0,
+            prompt_config,
+            pack: default_pack(),
+            prompts,
+            context_config: config.get_context().clone(),
+            usage: UsageTracker::new(config.get_prices().clone()),
+            save_path: String::from(config.get_save_path()),
             log,
         }
     }
 
-    pub fn create_game(&mut self) {
-        let client = reqwest::blocking::Client::new();
+    /// The prompt packs a new game can be started with.
+    pub fn get_pack_names(&self) -> Vec<String> {
+        self.prompt_config.pack_names()
+    }
 
-        let body = json!({
-            "model": "gpt-4-1106-preview",
-            "messages": [
-                {
-                    "role": "system",
-                    "content": "You are a text adventure game designer. Your job is to come up with a new game idea that would work well as a text adventure game, and provide a single-paragraph overview of the setting, the goal, characters, and any rules. This overview will be used in subsequent requests to a less-powerful LLM as part of the context for generating parts of the game, so be sure to provide enough information, but not too much detail, so that a weaker LLM can remain focused with its gameplay narratives. Some potential topics might include a fantasy adventure, a sci-fi adventure, a mystery, or a horror story. Some example settings might include a desert, spaceship, castle, or haunted house. Some example goals might include finding a treasure, escaping a monster, traveling between planets in space, or solving a mystery. Some example characters might include a shopkeeper, ship captain, companion, or ghost. Some example rules might include magic, technology, or a curse."
-                },
-                {
-                    "role": "user",
-                    "content": "please provide a game overview"
-                }
-            ]
-        });
+    /// Starts a new game with the named prompt pack and plays its opening turn, which sets the
+    /// first scene.
+    pub fn start_game(
+        &mut self,
+        pack: &str,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Vec<Action> {
+        self.pack = String::from(pack);
+        self.prompts = self
+            .prompt_config
+            .build(pack, self.backend.response_format());
+        self.create_game(cancellation, on_progress);
+        self.play_turn(None, &GameState::new(), cancellation, on_progress)
+    }
 
-        writeln!(
-            self.log,
-            "Sending request to OpenAI\n--------\n{}",
-            serde_json::to_string_pretty(&body).unwrap()
+    /// Generates the overview of a new game. If the backend fails, the game goes on without one.
+    fn create_game(&mut self, cancellation: &Cancellation, on_progress: &mut dyn FnMut(Progress)) {
+        let prompt = Prompt::new(
+            String::from(self.prompts.get_overview()),
+            vec![ChatMessage::user(String::from(
+                "please provide a game overview",
+            ))],
         )
-        .unwrap();
-        writeln!(self.log, "--------").unwrap();
+        .with_cancellation(cancellation.clone());
 
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .body(body.to_string())
-            .send()
-            .expect("Failed to send request.");
-
-        // Check if the response status is success
-        if response.status().is_success() {
-            let response_json: Value = response.json().expect("Failed to parse response.");
-            let mut response_text = response_json["choices"][0]["message"]["content"]
-                .as_str()
-                .expect("Failed to parse response.");
-            self.overview = response_text.to_string();
-            writeln!(
-                self.log,
-                "Received response from OpenAI\n--------\n{:?}",
-                &response_text
-            )
-            .unwrap();
-            writeln!(self.log, "--------").unwrap();
-        } else {
-            panic!(
-                "Failed to get a successful response: {:?}",
-                response.status()
-            );
+        self.log_request(&prompt);
+        let result = self.backend.generate_overview(
+            &prompt,
+            &mut track_progress(&mut self.usage, &mut self.log, on_progress),
+        );
+        let overview = match result {
+            Ok(overview) => {
+                self.log_response(&overview);
+                overview
+            }
+            Err(e) => {
+                self.log_response(&e.to_string());
+                String::new()
+            }
+        };
+
+        self.overview = overview;
+        self.action_history.clear();
+        self.pending_turn = None;
+        self.memory = Memory::default();
+        self.evicted = 0;
+    }
+
+    /// Restores the game from the save file, returning its state, if there is one.
+    pub fn resume(&mut self) -> Option<GameState> {
+        let save = fs::read_to_string(&self.save_path).ok()?;
+        let save: SaveGame = match serde_yaml::from_str(&save) {
+            Ok(save) => save,
+            Err(e) => {
+                writeln!(self.log, "Failed to read save game: {}", e).unwrap();
+                return None;
+            }
+        };
+
+        self.prompts = self
+            .prompt_config
+            .build(&save.pack, self.backend.response_format());
+        self.pack = save.pack;
+        self.overview = save.overview;
+        self.action_history = save.history;
+        self.pending_turn = None;
+        self.memory = save.memory;
+        self.evicted = 0;
+        Some(save.state)
+    }
+
+    pub fn save(&mut self, state: &GameState) {
+        let save = SaveGame {
+            pack: self.pack.clone(),
+            overview: self.overview.clone(),
+            memory: self.memory.clone(),
+            history: self.action_history.clone(),
+            state: state.clone(),
+        };
+        if let Err(e) = fs::write(&self.save_path, serde_yaml::to_string(&save).unwrap()) {
+            writeln!(self.log, "Failed to save game: {}", e).unwrap();
         }
     }
 
-    pub fn process_input(&mut self, input: &str, state: &GameState) -> Action {
-        let context = Context::new(
-            self.overview.clone(),
-            state.get_inventory().clone(),
-            self.action_history.clone(),
-            String::from(input),
+    pub fn process_input(
+        &mut self,
+        input: &str,
+        state: &GameState,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Vec<Action> {
+        self.play_turn(Some(input), state, cancellation, on_progress)
+    }
+
+    /// Plays a turn with the player's input, or the opening turn if there is none. Any summary
+    /// that is due is made first, as part of the turn, so cancelling the turn cancels it too. A
+    /// cancelled turn stops as soon as the backend notices, and has no actions.
+    fn play_turn(
+        &mut self,
+        input: Option<&str>,
+        state: &GameState,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Vec<Action> {
+        self.pending_turn = None;
+        self.usage.start_turn();
+        self.update_memory(cancellation, on_progress);
+        let turn = match input {
+            Some(_) => self.action_history.last().map_or(1, |item| item.turn + 1),
+            None => 0,
+        };
+        let prompt_input = input.unwrap_or(START_INPUT);
+
+        let budget = self.context_config.budget_for(self.backend.action_model());
+        let mut builder = ContextBuilder::new(budget);
+        if self.context_config.get_mode() == ContextMode::Chat {
+            builder = builder.with_batch(SUMMARY_BATCH);
+        }
+        let (context, report) = builder.build(
+            &self.overview,
+            self.memory.get_summary(),
+            state,
+            self.memory.unsummarized(&self.action_history),
+            prompt_input,
         );
-        let context = serde_yaml::to_string(&context).unwrap();
-        let action = self.send_openai_request(&context).unwrap();
-        self.add_action_to_history(action.clone());
-        action
+        writeln!(self.log, "Context: {}", report).unwrap();
+        self.evicted = report.get_dropped();
+        let messages = context.to_messages(self.context_config.get_mode());
+        let prompt = Prompt::new(String::from(self.prompts.get_turn()), messages)
+            .with_input(String::from(prompt_input))
+            .with_cancellation(cancellation.clone());
+
+        // Failed turns are not kept in the history, so the model never sees these messages.
+        match self.request_action(prompt, on_progress) {
+            Ok(actions) => {
+                self.pending_turn = Some(ActionHistoryItem::new(
+                    turn,
+                    input.map(String::from),
+                    actions.clone(),
+                ));
+                actions
+            }
+            Err(BackendError::Cancelled) => Vec::new(),
+            Err(BackendError::Parse { .. }) => vec![Action::Information {
+                message: String::from(
+                    "Nothing seems to happen. Perhaps try something else, or describe it differently.",
+                ),
+            }],
+            Err(e) => vec![Action::Information {
+                message: format!(
+                    "The storyteller could not be reached ({}). Please try again in a moment.",
+                    e
+                ),
+            }],
+        }
+    }
+
+    /// Requests the actions for a turn from the backend. When the backend replies with something that cannot
+    /// be parsed, the model is shown its reply and the parse error and asked to correct it, up to
+    /// `MAX_REPAIR_ATTEMPTS` times.
+    fn request_action(
+        &mut self,
+        mut prompt: Prompt,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<Vec<Action>, BackendError> {
+        let mut attempt = 0;
+        loop {
+            prompt.get_cancellation().check()?;
+            self.log_request(&prompt);
+            let result = self.backend.generate_action(
+                &prompt,
+                &mut track_progress(&mut self.usage, &mut self.log, on_progress),
+            );
+            match result {
+                Ok(actions) => {
+                    self.log_response(&actions);
+                    return Ok(actions);
+                }
+                Err(BackendError::Parse { output, error }) if attempt < MAX_REPAIR_ATTEMPTS => {
+                    attempt += 1;
+                    self.log_response(&format!("Unparseable response ({}): {}", error, output));
+                    prompt.push_message(ChatMessage::assistant(output));
+                    prompt.push_message(ChatMessage::user(format!(
+                        "Your response could not be parsed as a list of actions: {}\n\nPlease respond again with only the corrected actions, in the format described above.",
+                        error
+                    )));
+                }
+                Err(e) => {
+                    self.log_response(&e.to_string());
+                    return Err(e);
+                }
+            }
+        }
     }
 
-    fn add_action_to_history(&mut self, action: Action) {
-        let history_limit = 12;
-        self.action_history
-            .push(ActionHistoryItem::new(String::from(""), action));
+    /// The tokens used by the last turn and the session so far.
+    pub fn get_usage(&self) -> UsageReport {
+        self.usage.get_report()
+    }
 
-        if self.action_history.len() > history_limit {
-            self.action_history.remove(0);
+    /// Adds the last turn played to the history. Turns that are never committed, such as cancelled
+    /// turns, are forgotten by the next turn played.
+    pub fn commit(&mut self) {
+        if let Some(item) = self.pending_turn.take() {
+            self.add_turn_to_history(item);
         }
     }
 
-    fn send_openai_request(&mut self, input: &str) -> Result<Action, ()> {
-        let client = reqwest::blocking::Client::new();
+    /// Folds the history entries that did not fit into the last context into the summary, once
+    /// enough of them have piled up. If the backend fails or the turn is cancelled, they are tried
+    /// again next time.
+    fn update_memory(
+        &mut self,
+        cancellation: &Cancellation,
+        on_progress: &mut dyn FnMut(Progress),
+    ) {
+        let unsummarized = self.memory.unsummarized(&self.action_history);
+        let evicted = &unsummarized[..self.evicted.min(unsummarized.len())];
+        if evicted.len() < SUMMARY_BATCH || cancellation.is_cancelled() {
+            return;
+        }
 
-        let body = json!({
-            "model": "gpt-3.5-turbo",
-            "messages": [
-                {
-                    "role": "system",
-                    "content": self.system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": input
-                }
-            ]
-        });
+        let count = evicted.len();
+        let prompt = self
+            .memory
+            .summary_prompt(self.prompts.get_summary(), evicted)
+            .with_cancellation(cancellation.clone());
+        self.log_request(&prompt);
+        let result = self.backend.generate_summary(
+            &prompt,
+            &mut track_progress(&mut self.usage, &mut self.log, on_progress),
+        );
+        match result {
+            Ok(summary) => {
+                self.log_response(&summary);
+                self.memory.update(summary, count);
+                self.evicted = 0;
+            }
+            Err(e) => self.log_response(&e.to_string()),
+        }
+    }
 
+    /// The whole history is kept. `ContextBuilder` and `Memory` decide how much of it the model
+    /// sees, and how.
+    fn add_turn_to_history(&mut self, item: ActionHistoryItem) {
+        self.action_history.push(item);
+    }
+
+    fn log_request(&mut self, prompt: &Prompt) {
         writeln!(
             self.log,
-            "Sending request to OpenAI\n--------\n{}",
-            serde_json::to_string_pretty(&body).unwrap()
+            "Sending request to {}\n--------\n{}",
+            self.backend.request_name(),
+            serde_yaml::to_string(prompt).unwrap()
         )
         .unwrap();
         writeln!(self.log, "--------").unwrap();
+    }
 
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .body(body.to_string())
-            .send()
-            .expect("Failed to send request.");
-
-        // Check if the response status is success
-        if response.status().is_success() {
-            let response_json: Value = response.json().expect("Failed to parse response.");
-            let mut response_text = response_json["choices"][0]["message"]["content"]
-                .as_str()
-                .expect("Failed to parse response.");
-
-            if response_text.starts_with("response:\n") {
-                response_text = response_text.trim_start_matches("response:\n").trim();
-            }
+    fn log_response(&mut self, response: &impl std::fmt::Debug) {
+        writeln!(
+            self.log,
+            "Received response from {}\n--------\n{:?}",
+            self.backend.name(),
+            response
+        )
+        .unwrap();
+        writeln!(self.log, "--------").unwrap();
+    }
+}
 
+/// Records the usage reported by the backend in the tracker and logs any fallback to another
+/// backend, and passes on any other progress.
+fn track_progress<'a>(
+    usage: &'a mut UsageTracker,
+    log: &'a mut Box<dyn Write + Send>,
+    on_progress: &'a mut dyn FnMut(Progress),
+) -> impl FnMut(Progress) + 'a {
+    move |progress| match progress {
+        Progress::Usage(report) => usage.record(&report),
+        Progress::Fallback {
+            ref failed,
+            ref next,
+            ref error,
+        } => {
             writeln!(
-                self.log,
-                "Received response from OpenAI\n--------\n{:?}",
-                &response_text
+                log,
+                "{} failed, falling back to {}: {}",
+                failed, next, error
             )
             .unwrap();
-            writeln!(self.log, "--------").unwrap();
-            let action = Action::from_yaml(&response_text).expect("Failed to parse response.");
-            return Ok(action);
-        } else {
-            panic!(
-                "Failed to get a successful response: {:?}",
-                response.status()
+            on_progress(progress);
+        }
+        progress => on_progress(progress),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use self::backend::MockBackend;
+
+    use super::*;
+
+    /// Fails to parse a set number of times before returning an action, recording each prompt.
+    struct FlakyBackend {
+        failures: usize,
+        prompts: Arc<Mutex<Vec<Prompt>>>,
+    }
+
+    impl Backend for FlakyBackend {
+        fn name(&self) -> &str {
+            "Flaky"
+        }
+
+        fn generate_overview(
+            &mut self,
+            _prompt: &Prompt,
+            _on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<String, BackendError> {
+            Ok(String::from("An overview."))
+        }
+
+        fn generate_summary(
+            &mut self,
+            _prompt: &Prompt,
+            _on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<String, BackendError> {
+            Ok(String::from("A summary."))
+        }
+
+        fn generate_action(
+            &mut self,
+            prompt: &Prompt,
+            _on_progress: &mut dyn FnMut(Progress),
+        ) -> Result<Vec<Action>, BackendError> {
+            self.prompts.lock().unwrap().push(prompt.clone());
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(BackendError::Parse {
+                    output: String::from("type: Dance"),
+                    error: String::from("unknown variant `Dance`"),
+                });
+            }
+            Ok(vec![Action::Information {
+                message: String::from("You look around."),
+            }])
+        }
+    }
+
+    fn flaky_loader(failures: usize) -> (GameLoader, Arc<Mutex<Vec<Prompt>>>) {
+        let prompts = Arc::new(Mutex::new(Vec::new()));
+        let backend = FlakyBackend {
+            failures,
+            prompts: prompts.clone(),
+        };
+        let loader = GameLoader::with_log(
+            &Config::default(),
+            Box::new(backend),
+            Box::new(std::io::sink()),
+        );
+        (loader, prompts)
+    }
+
+    #[test]
+    fn loader_repairs_unparseable_action() {
+        let (mut loader, prompts) = flaky_loader(MAX_REPAIR_ATTEMPTS);
+
+        let action = loader.process_input(
+            "look around",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.commit();
+
+        let expected = vec![Action::Information {
+            message: String::from("You look around."),
+        }];
+        assert_eq!(action, expected);
+        assert_eq!(loader.action_history.len(), 1);
+
+        let prompts = prompts.lock().unwrap();
+        assert_eq!(prompts.len(), MAX_REPAIR_ATTEMPTS + 1);
+        let repair_messages = prompts.last().unwrap().get_messages();
+        assert_eq!(repair_messages.len(), 1 + 2 * MAX_REPAIR_ATTEMPTS);
+    }
+
+    #[test]
+    fn loader_falls_back_when_repairs_run_out() {
+        let (mut loader, prompts) = flaky_loader(MAX_REPAIR_ATTEMPTS + 1);
+
+        let action = loader.process_input(
+            "look around",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.commit();
+
+        assert!(matches!(action[..], [Action::Information { .. }]));
+        assert!(loader.action_history.is_empty());
+        assert_eq!(prompts.lock().unwrap().len(), MAX_REPAIR_ATTEMPTS + 1);
+    }
+
+    #[test]
+    fn loader_stops_cancelled_turn() {
+        let (mut loader, prompts) = flaky_loader(MAX_REPAIR_ATTEMPTS);
+        let cancellation = Cancellation::default();
+        cancellation.cancel();
+
+        let actions =
+            loader.process_input("look around", &GameState::new(), &cancellation, &mut |_| {});
+        loader.commit();
+
+        assert!(actions.is_empty());
+        assert!(loader.action_history.is_empty());
+        assert!(prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn loader_forgets_uncommitted_action() {
+        let (mut loader, _) = flaky_loader(0);
+
+        loader.process_input(
+            "look around",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.process_input(
+            "look again",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.commit();
+        loader.commit();
+
+        assert_eq!(loader.action_history.len(), 1);
+    }
+
+    #[test]
+    fn loader_records_complete_turns() {
+        let (mut loader, prompts) = flaky_loader(0);
+
+        loader.start_game(DEFAULT_PACK, &Cancellation::default(), &mut |_| {});
+        loader.commit();
+        loader.process_input(
+            "look around",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.commit();
+        loader.process_input(
+            "open the door",
+            &GameState::new(),
+            &Cancellation::default(),
+            &mut |_| {},
+        );
+        loader.commit();
+
+        let turns: Vec<_> = loader
+            .action_history
+            .iter()
+            .map(|item| (item.get_turn(), item.get_input()))
+            .collect();
+        assert_eq!(
+            turns,
+            vec![
+                (0, None),
+                (1, Some("look around")),
+                (2, Some("open the door"))
+            ]
+        );
+        assert!(loader.action_history.iter().all(|item| item.timestamp > 0));
+
+        let prompts = prompts.lock().unwrap();
+        assert_eq!(prompts[0].get_input(), Some(START_INPUT));
+        let context = &prompts.last().unwrap().get_messages()[0];
+        let context = serde_yaml::to_string(context).unwrap();
+        assert!(context.contains("look around"));
+        assert!(!context.contains("timestamp"));
+    }
+
+    #[test]
+    fn loader_summarizes_evicted_history() {
+        let script = r#"
+            summary: The player looked around a lot.
+            fallback:
+              type: Information
+              message: You look around, but the room is exactly as you left it a moment ago.
+        "#;
+        let backend = MockBackend::from_yaml(script).unwrap();
+        let config = Config::from_yaml("context: { budget: 100 }").unwrap();
+        let mut loader =
+            GameLoader::with_log(&config, Box::new(backend), Box::new(std::io::sink()));
+
+        for _ in 0..8 {
+            loader.process_input(
+                "look around",
+                &GameState::new(),
+                &Cancellation::default(),
+                &mut |_| {},
             );
+            loader.commit();
         }
+
+        assert_eq!(
+            loader.memory.get_summary(),
+            "The player looked around a lot."
+        );
+        assert_eq!(loader.action_history.len(), 8);
+        let unsummarized = loader.memory.unsummarized(&loader.action_history).len();
+        assert!(unsummarized <= 8 - SUMMARY_BATCH);
+
+        let prompt = loader
+            .memory
+            .summary_prompt("", &loader.action_history[..SUMMARY_BATCH]);
+        let request = serde_yaml::to_string(&prompt.get_messages()[0]).unwrap();
+        assert!(request.contains("look around"));
+        assert!(!request.contains("timestamp"));
+    }
+
+    #[test]
+    fn loader_summarizes_within_the_next_turn() {
+        let script = r#"
+            summary: The player looked around a lot.
+            fallback:
+              type: Information
+              message: You look around, but the room is exactly as you left it a moment ago.
+        "#;
+        let backend = MockBackend::from_yaml(script).unwrap();
+        let config = Config::from_yaml("context: { budget: 100 }").unwrap();
+        let mut loader =
+            GameLoader::with_log(&config, Box::new(backend), Box::new(std::io::sink()));
+        let state = GameState::new();
+
+        while loader.evicted < SUMMARY_BATCH {
+            loader.process_input("look around", &state, &Cancellation::default(), &mut |_| {});
+            loader.commit();
+        }
+        assert_eq!(loader.memory.get_summary(), "");
+
+        let cancelled = Cancellation::default();
+        cancelled.cancel();
+        assert!(loader
+            .process_input("look around", &state, &cancelled, &mut |_| {})
+            .is_empty());
+        assert_eq!(loader.memory.get_summary(), "");
+
+        loader.process_input("look around", &state, &Cancellation::default(), &mut |_| {});
+        assert_eq!(
+            loader.memory.get_summary(),
+            "The player looked around a lot."
+        );
     }
 }
@@ -0,0 +1,243 @@
+// Prompts are built from templates, which are embedded in the binary and can be overridden from
+// files, and filled in with the variables of the prompt pack picked for the game
+
+use std::{collections::BTreeMap, fs, path::Path, sync::OnceLock};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use super::backend::ResponseFormat;
+
+const OVERVIEW_TEMPLATE: &str = include_str!("../../prompts/overview.txt");
+const TURN_TEMPLATE: &str = include_str!("../../prompts/turn.txt");
+const SUMMARY_TEMPLATE: &str = include_str!("../../prompts/summary.txt");
+
+/// The pack used when the player does not pick one.
+pub const DEFAULT_PACK: &str = "default";
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct PromptConfig {
+    /// A directory of templates that override the embedded ones: `overview.txt`, `turn.txt` and
+    /// `summary.txt`. Templates missing from it are taken from the binary.
+    dir: Option<String>,
+    /// Named packs the player can pick from when starting a new game.
+    packs: BTreeMap<String, PromptPack>,
+}
+
+/// The variables a pack fills the templates in with, and its own template overrides.
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct PromptPack {
+    /// Templates in this directory take precedence over those in `PromptConfig::dir`.
+    #[serde(default)]
+    dir: Option<String>,
+    /// Values for `{{name}}` placeholders in the templates, such as `genre`, `tone`, `person` and
+    /// `language`. Variables that are not set keep their defaults. `format` is always filled in
+    /// from the backend, as it has to match the way actions are requested.
+    #[serde(flatten)]
+    variables: BTreeMap<String, String>,
+}
+
+/// The prompts for a game, with every variable filled in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompts {
+    overview: String,
+    turn: String,
+    summary: String,
+}
+
+impl PromptConfig {
+    /// The names of the packs to pick from, with the default pack first.
+    pub fn pack_names(&self) -> Vec<String> {
+        let mut names = vec![String::from(DEFAULT_PACK)];
+        names.extend(
+            self.packs
+                .keys()
+                .filter(|name| *name != DEFAULT_PACK)
+                .cloned(),
+        );
+        names
+    }
+
+    /// Builds the prompts for the named pack, telling the model to reply in the given format. An
+    /// unknown name gets the default variables, so an old save game still loads after its pack is
+    /// removed from the config.
+    pub fn build(&self, pack_name: &str, format: ResponseFormat) -> Prompts {
+        let pack = self.packs.get(pack_name).cloned().unwrap_or_default();
+        let mut variables = default_variables();
+        variables.extend(pack.variables);
+        variables.insert(String::from("format"), format_instructions(format));
+
+        let dirs: Vec<&str> = [pack.dir.as_deref(), self.dir.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let template = |file: &str, embedded: &str| {
+            let template = dirs
+                .iter()
+                .find_map(|dir| fs::read_to_string(Path::new(dir).join(file)).ok())
+                .unwrap_or(String::from(embedded));
+            render(&template, &variables)
+        };
+
+        Prompts {
+            overview: template("overview.txt", OVERVIEW_TEMPLATE),
+            turn: template("turn.txt", TURN_TEMPLATE),
+            summary: template("summary.txt", SUMMARY_TEMPLATE),
+        }
+    }
+}
+
+impl Prompts {
+    pub fn get_overview(&self) -> &str {
+        &self.overview
+    }
+
+    pub fn get_turn(&self) -> &str {
+        &self.turn
+    }
+
+    pub fn get_summary(&self) -> &str {
+        &self.summary
+    }
+}
+
+fn default_variables() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            String::from("genre"),
+            String::from("any - fantasy, sci-fi, mystery and horror all work well"),
+        ),
+        (
+            String::from("tone"),
+            String::from("mysterious and suspenseful"),
+        ),
+        (String::from("person"), String::from("second")),
+        (String::from("language"), String::from("English")),
+    ])
+}
+
+/// What the turn prompt says about the shape of the reply, for the `{{format}}` variable.
+fn format_instructions(format: ResponseFormat) -> String {
+    let instructions = match format {
+        ResponseFormat::Yaml => {
+            "Your response should be a YAML list of one or more actions, which the game applies in order."
+        }
+        ResponseFormat::Tools => {
+            "Respond by calling one tool per action, named after the action type, with the other properties as its arguments. Call several tools when one command has several effects, and the game applies them in order. The example responses below are written as YAML for brevity, but always call tools instead of writing them out."
+        }
+        ResponseFormat::Json => {
+            "Your response should be a JSON object with an actions property, holding a list of one or more actions, which the game applies in order. The example responses below are written as YAML for brevity, but always reply with the JSON object."
+        }
+    };
+    String::from(instructions)
+}
+
+/// Replaces each `{{name}}` in the template with the value of its variable. Placeholders without
+/// a variable are left as they are. The template is filled in a single pass, so placeholders in
+/// the values themselves are left alone.
+fn render(template: &str, variables: &BTreeMap<String, String>) -> String {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let placeholder = PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{(\w+)\}\}").unwrap());
+    placeholder
+        .replace_all(template, |captures: &Captures| {
+            match variables.get(&captures[1]) {
+                Some(value) => value.clone(),
+                None => String::from(&captures[0]),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prompts_fill_in_default_variables() {
+        let prompts = PromptConfig::default().build(DEFAULT_PACK, ResponseFormat::Yaml);
+
+        assert!(prompts
+            .get_turn()
+            .contains("in the second person, in English"));
+        assert!(prompts
+            .get_overview()
+            .contains("Tone: mysterious and suspenseful."));
+        assert!(!prompts.get_summary().contains("{{"));
+    }
+
+    #[test]
+    fn prompts_use_pack_variables_and_templates() {
+        // Unique to the test and the run, so that neither other runs nor leftovers get in the way.
+        let dir = std::env::temp_dir().join(format!(
+            "adventui-test-uses-pack-variables-and-templates-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("overview.txt"),
+            "Invent a {{genre}} story, {{audience}}.",
+        )
+        .unwrap();
+        let config: PromptConfig = serde_yaml::from_str(&format!(
+            r#"
+                packs:
+                  noir:
+                    dir: {}
+                    genre: hard-boiled detective
+                    person: first
+                    audience: for adults
+            "#,
+            dir.to_str().unwrap()
+        ))
+        .unwrap();
+
+        let prompts = config.build("noir", ResponseFormat::Yaml);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            prompts.get_overview(),
+            "Invent a hard-boiled detective story, for adults."
+        );
+        assert!(prompts
+            .get_turn()
+            .contains("in the first person, in English"));
+        assert_eq!(config.pack_names(), vec!["default", "noir"]);
+        assert_eq!(
+            config.build("missing", ResponseFormat::Yaml),
+            PromptConfig::default().build(DEFAULT_PACK, ResponseFormat::Yaml)
+        );
+    }
+
+    #[test]
+    fn prompts_describe_the_response_format() {
+        let config: PromptConfig = serde_yaml::from_str("packs: { odd: { format: XML } }").unwrap();
+        let yaml = config.build("odd", ResponseFormat::Yaml);
+        let tools = config.build("odd", ResponseFormat::Tools);
+        let json = config.build("odd", ResponseFormat::Json);
+
+        assert!(yaml
+            .get_turn()
+            .contains("a YAML list of one or more actions"));
+        assert!(!tools.get_turn().contains("YAML list"));
+        assert!(tools.get_turn().contains("calling one tool per action"));
+        assert!(!json.get_turn().contains("YAML list"));
+        assert!(json
+            .get_turn()
+            .contains("a JSON object with an actions property"));
+        assert!(!yaml.get_turn().contains("XML"));
+    }
+
+    #[test]
+    fn prompts_leave_placeholders_in_values_alone() {
+        let variables = BTreeMap::from([
+            (String::from("genre"), String::from("a {{tone}} western")),
+            (String::from("tone"), String::from("grim")),
+        ]);
+
+        assert_eq!(
+            render("{{genre}}, {{tone}}, {{missing}}", &variables),
+            "a {{tone}} western, grim, {{missing}}"
+        );
+    }
+}
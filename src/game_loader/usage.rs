@@ -0,0 +1,137 @@
+// Usage tracking adds up the tokens reported by the backend for each turn and the whole session,
+// and estimates what they cost
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::Deserialize;
+
+use super::backend::Usage;
+
+/// Prices per model, keyed by model name.
+pub type PriceTable = BTreeMap<String, Price>;
+
+/// The price of a model in dollars per million tokens.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct Price {
+    prompt: f64,
+    completion: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TokenCount {
+    prompt: u64,
+    completion: u64,
+    /// The estimated cost in dollars, for the models with a price.
+    cost: f64,
+    /// Whether any of the tokens were used by a model without a price.
+    unpriced: bool,
+}
+
+impl TokenCount {
+    fn add(&mut self, usage: &Usage, price: Option<&Price>) {
+        self.prompt += usage.get_prompt_tokens();
+        self.completion += usage.get_completion_tokens();
+        match price {
+            Some(price) => {
+                self.cost += (usage.get_prompt_tokens() as f64 * price.prompt
+                    + usage.get_completion_tokens() as f64 * price.completion)
+                    / 1_000_000.0
+            }
+            None => self.unpriced = true,
+        }
+    }
+}
+
+impl fmt::Display for TokenCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in / {} out", self.prompt, self.completion)?;
+        if self.cost > 0.0 || !self.unpriced {
+            write!(f, ", ${:.4}", self.cost)?;
+        }
+        if self.unpriced {
+            write!(f, " (some models unpriced)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The tokens used by the last turn and by the session so far, shown in the status line.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UsageReport {
+    turn: TokenCount,
+    session: TokenCount,
+}
+
+impl fmt::Display for UsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Turn: {} | Session: {}", self.turn, self.session)
+    }
+}
+
+/// Everything since the game was launched counts towards the session, including cancelled turns
+/// and summaries.
+pub struct UsageTracker {
+    prices: PriceTable,
+    report: UsageReport,
+}
+
+impl UsageTracker {
+    pub fn new(prices: PriceTable) -> Self {
+        UsageTracker {
+            prices,
+            report: UsageReport::default(),
+        }
+    }
+
+    pub fn start_turn(&mut self) {
+        self.report.turn = TokenCount::default();
+    }
+
+    pub fn record(&mut self, usage: &Usage) {
+        let price = self.prices.get(usage.get_model());
+        self.report.turn.add(usage, price);
+        self.report.session.add(usage, price);
+    }
+
+    pub fn get_report(&self) -> UsageReport {
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn usage_adds_up_turns_and_session() {
+        let prices: PriceTable = serde_yaml::from_str(
+            r#"
+                gpt-4-1106-preview: { prompt: 10.0, completion: 30.0 }
+            "#,
+        )
+        .unwrap();
+        let mut tracker = UsageTracker::new(prices);
+
+        tracker.start_turn();
+        tracker.record(&Usage::new("gpt-4-1106-preview", 1000, 200));
+        tracker.start_turn();
+        tracker.record(&Usage::new("gpt-4-1106-preview", 2000, 100));
+
+        let report = tracker.get_report();
+        assert_eq!(report.turn.prompt, 2000);
+        assert_eq!(report.session.prompt, 3000);
+        assert_eq!(report.session.completion, 300);
+        assert!((report.session.cost - 0.039).abs() < 1e-9);
+        assert_eq!(
+            report.to_string(),
+            "Turn: 2000 in / 100 out, $0.0230 | Session: 3000 in / 300 out, $0.0390"
+        );
+
+        tracker.record(&Usage::new("local-model", 10, 10));
+        assert_eq!(
+            tracker.get_report().session.to_string(),
+            "3010 in / 310 out, $0.0390 (some models unpriced)"
+        );
+    }
+}
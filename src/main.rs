@@ -1,5 +1,6 @@
 use std::io::stdout;
 
+use config::Config;
 use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
@@ -11,18 +12,21 @@ use message::Message;
 use ui::UI;
 
 mod action;
+mod config;
 mod dispatcher;
 mod game_loader;
 mod message;
 mod model;
 mod ui;
+mod worker;
 
 fn main() -> std::io::Result<()> {
+    let config = Config::load();
+    let loader = GameLoader::new(&config);
+
     stdout().execute(EnterAlternateScreen)?;
     enable_raw_mode()?;
 
-    let loader = GameLoader::new();
-
     let (app_state_tx, app_state_rx) = std::sync::mpsc::channel();
     let (ui_state_tx, ui_state_rx) = std::sync::mpsc::channel();
 
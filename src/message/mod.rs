@@ -1,6 +1,52 @@
-use crate::model::Model;
+use crate::{
+    action::Action,
+    game_loader::{
+        backend::{Cancellation, Progress},
+        usage::UsageReport,
+    },
+    model::{game_state::GameState, Model},
+};
 
 pub enum Message {
-    StateUpdate(Model),
+    StateUpdate(Box<Model>),
     Terminate,
 }
+
+/// Requests sent from the dispatcher to the worker that runs LLM calls. Turns carry a
+/// cancellation that the dispatcher sets when the player cancels them.
+pub enum TurnRequest {
+    /// Starts a new game with the named prompt pack.
+    StartGame {
+        id: u64,
+        pack: String,
+        cancellation: Cancellation,
+    },
+    Input {
+        id: u64,
+        input: String,
+        state: Box<GameState>,
+        cancellation: Cancellation,
+    },
+    /// Continues the saved game, if there is one.
+    Resume { id: u64, cancellation: Cancellation },
+    /// Keeps the last completed turn in the loader history and saves the game with the state it
+    /// led to. Cancelled turns are never committed.
+    Commit { state: Box<GameState> },
+}
+
+/// Events sent from the worker back to the dispatcher, tagged with the id of their turn.
+pub enum TurnEvent {
+    /// Streamed text or retries from the backend.
+    Progress { id: u64, progress: Progress },
+    /// The actions the turn resolved to, in order, and the tokens used so far.
+    Done {
+        id: u64,
+        actions: Vec<Action>,
+        usage: UsageReport,
+    },
+    /// The state of the saved game, or `None` if there is nothing to continue.
+    Resumed {
+        id: u64,
+        state: Option<Box<GameState>>,
+    },
+}
@@ -1,12 +1,40 @@
-#[derive(Debug, Clone, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    item::{self, Item},
+    stats::{Stat, Stats},
+    world::World,
+};
+
+/// Fields that only matter while the game is on screen are skipped when saving.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GameState {
-    inventory: Vec<String>,
+    inventory: Vec<Item>,
     scene_name: String,
     scene_desc: String,
+    #[serde(skip)]
     user_entry: String,
     entry_enabled: bool,
     scene_history: Vec<String>,
+    /// Every scene visited so far. Saves from before the world was kept start with an empty one.
+    #[serde(default)]
+    world: World,
+    /// Saves from before stats were kept start with full health and stamina.
+    #[serde(default)]
+    stats: Stats,
+    #[serde(skip)]
+    pending_response: Option<String>,
+    #[serde(skip)]
+    thinking: bool,
+    #[serde(skip)]
+    thinking_status: Option<String>,
+    #[serde(skip)]
+    spinner_frame: usize,
+    #[serde(skip)]
     scroll_position: usize,
+    /// Token usage and cost, shown in the status line.
+    #[serde(skip)]
+    usage_status: String,
 }
 
 impl GameState {
@@ -18,7 +46,14 @@ impl GameState {
             user_entry: String::new(),
             entry_enabled: true,
             scene_history: Vec::new(),
+            world: World::new(),
+            stats: Stats::new(),
+            pending_response: None,
+            thinking: false,
+            thinking_status: None,
+            spinner_frame: 0,
             scroll_position: 0,
+            usage_status: String::new(),
         }
     }
 
@@ -43,6 +78,37 @@ impl GameState {
         self.scroll_reset();
     }
 
+    /// Sets the narrative of a response that is still arriving, shown after the scene history.
+    pub fn set_pending_response(&mut self, s: String) {
+        self.pending_response = Some(s);
+        self.scroll_reset();
+    }
+
+    /// Marks a turn as pending, showing a thinking indicator in place of the input.
+    pub fn start_thinking(&mut self) {
+        self.thinking = true;
+        self.spinner_frame = 0;
+    }
+
+    pub fn stop_thinking(&mut self) {
+        self.thinking = false;
+        self.thinking_status = None;
+        self.pending_response = None;
+    }
+
+    /// Replaces the thinking indicator's message, such as while a request is being retried.
+    pub fn set_thinking_status(&mut self, status: String) {
+        self.thinking_status = Some(status);
+    }
+
+    pub fn set_usage_status(&mut self, status: String) {
+        self.usage_status = status;
+    }
+
+    pub fn tick_spinner(&mut self) {
+        self.spinner_frame = self.spinner_frame.wrapping_add(1);
+    }
+
     pub fn scroll_up(&mut self, amount: usize) {
         self.scroll_position += amount;
     }
@@ -59,19 +125,57 @@ impl GameState {
         self.scroll_position = 0;
     }
 
+    /// Moves the player to a scene. Returning to a scene visited before restores its name and
+    /// description, rather than taking the ones given.
     pub fn new_scene(&mut self, name: String, desc: String) {
-        self.scene_name = name;
-        self.scene_desc = desc;
+        let scene = self.world.enter(&name, &desc);
+        self.scene_name = String::from(scene.get_name());
+        self.scene_desc = String::from(scene.get_desc());
         self.scene_history.clear();
         self.scroll_reset();
     }
 
-    pub fn add_to_inventory(&mut self, item: String) {
-        self.inventory.push(item);
+    /// Records a message as something that happened in the current scene.
+    pub fn record_event(&mut self, message: &str) {
+        self.world.record_event(message);
+    }
+
+    /// Adds an item to the inventory, taking it from the current scene if it was lying there. An
+    /// item taken from the scene keeps its description and tags.
+    pub fn add_to_inventory(&mut self, mut item: Item) {
+        if let Some(found) = self.world.take_item(item.get_name(), item.get_quantity()) {
+            item.fill_from(&found);
+        }
+        item::add(&mut self.inventory, item);
+    }
+
+    /// Removes all of an item from the inventory, leaving them in the current scene.
+    pub fn remove_from_inventory(&mut self, name: &str) {
+        if let Some(item) = item::take(&mut self.inventory, name, u32::MAX) {
+            self.world.add_item(item);
+        }
+    }
+
+    /// Changes how many of an item are in the inventory. Items taken away are used up, rather
+    /// than left in the scene. A change of zero does nothing.
+    pub fn change_quantity(&mut self, name: &str, change: i32) {
+        match u32::try_from(change) {
+            Ok(0) => {}
+            Ok(added) => item::add(&mut self.inventory, Item::new(name).with_quantity(added)),
+            Err(_) => {
+                item::take(&mut self.inventory, name, change.unsigned_abs());
+            }
+        }
+    }
+
+    /// Places an item in the current scene.
+    pub fn add_to_scene(&mut self, item: Item) {
+        self.world.add_item(item);
     }
 
-    pub fn remove_from_inventory(&mut self, item: String) {
-        self.inventory.retain(|i| i != &item);
+    /// Changes one of the player's stats, keeping it within its range.
+    pub fn modify_stat(&mut self, stat: Stat, change: i32) {
+        self.stats.modify(stat, change);
     }
 
     pub fn enable_entry(&mut self) {
@@ -104,7 +208,44 @@ impl GameState {
         &self.scene_history
     }
 
-    pub fn get_inventory(&self) -> &Vec<String> {
+    pub fn is_entry_enabled(&self) -> bool {
+        self.entry_enabled
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    pub fn get_thinking_status(&self) -> Option<&str> {
+        self.thinking_status.as_deref()
+    }
+
+    pub fn get_spinner_frame(&self) -> usize {
+        self.spinner_frame
+    }
+
+    pub fn get_usage_status(&self) -> &str {
+        &self.usage_status
+    }
+
+    pub fn get_pending_response(&self) -> Option<&str> {
+        self.pending_response.as_deref()
+    }
+
+    /// The held item with the given name, if there is one.
+    pub fn find_in_inventory(&self, name: &str) -> Option<&Item> {
+        self.inventory.iter().find(|item| item.is_named(name))
+    }
+
+    pub fn get_inventory(&self) -> &Vec<Item> {
         &self.inventory
     }
+
+    pub fn get_world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn get_stats(&self) -> &Stats {
+        &self.stats
+    }
 }
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// An item held by the player or lying in a scene. Several of the same item are kept as a single
+/// stack with a quantity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedItem")]
+pub struct Item {
+    name: String,
+    #[serde(skip_serializing_if = "is_one")]
+    quantity: u32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    desc: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// Saves from before items were structured store only their names.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedItem {
+    Name(String),
+    Item {
+        name: String,
+        #[serde(default = "one")]
+        quantity: u32,
+        #[serde(default)]
+        desc: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+impl From<SavedItem> for Item {
+    fn from(saved: SavedItem) -> Self {
+        match saved {
+            SavedItem::Name(name) => Item::new(&name),
+            SavedItem::Item {
+                name,
+                quantity,
+                desc,
+                tags,
+            } => Item {
+                name,
+                quantity,
+                desc,
+                tags,
+            },
+        }
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn is_one(quantity: &u32) -> bool {
+    *quantity == 1
+}
+
+impl Item {
+    pub fn new(name: &str) -> Self {
+        Item {
+            name: String::from(name),
+            quantity: 1,
+            desc: String::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_quantity(mut self, quantity: u32) -> Self {
+        self.quantity = quantity;
+        self
+    }
+
+    pub fn with_desc(mut self, desc: &str) -> Self {
+        self.desc = String::from(desc);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Whether the item has the given name. Models are not always consistent about case, so it
+    /// is ignored.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.to_lowercase() == name.to_lowercase()
+    }
+
+    /// Fills in the description and tags of this item from another copy of it, where this one
+    /// has none.
+    pub fn fill_from(&mut self, other: &Item) {
+        if self.desc.is_empty() {
+            self.desc = other.desc.clone();
+        }
+        for tag in &other.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_quantity(&self) -> u32 {
+        self.quantity
+    }
+
+    pub fn get_desc(&self) -> &str {
+        &self.desc
+    }
+}
+
+/// The name, followed by the quantity when there is more than one.
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.quantity {
+            1 => write!(f, "{}", self.name),
+            quantity => write!(f, "{} x{}", self.name, quantity),
+        }
+    }
+}
+
+/// Adds an item to a list, stacking it onto the item of the same name if there is one. Nothing is
+/// added when the quantity is zero.
+pub fn add(items: &mut Vec<Item>, item: Item) {
+    if item.quantity == 0 {
+        return;
+    }
+    match items.iter_mut().find(|held| held.is_named(&item.name)) {
+        Some(held) => {
+            held.quantity = held.quantity.saturating_add(item.quantity);
+            held.fill_from(&item);
+        }
+        None => items.push(item),
+    }
+}
+
+/// Takes up to `quantity` of the named item from a list, removing the item once none are left.
+/// Returns what was taken, if the item was there.
+pub fn take(items: &mut Vec<Item>, name: &str, quantity: u32) -> Option<Item> {
+    let index = items.iter().position(|held| held.is_named(name))?;
+    let held = &mut items[index];
+    if held.quantity > quantity {
+        held.quantity -= quantity;
+        return Some(held.clone().with_quantity(quantity));
+    }
+    Some(items.remove(index))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn item_stacks_by_name() {
+        let mut items = Vec::new();
+        add(&mut items, Item::new("Gold Coin").with_quantity(3));
+        add(
+            &mut items,
+            Item::new("gold coin")
+                .with_desc("A worn coin.")
+                .with_tags(vec![String::from("currency")]),
+        );
+
+        assert_eq!(
+            items,
+            vec![Item::new("Gold Coin")
+                .with_quantity(4)
+                .with_desc("A worn coin.")
+                .with_tags(vec![String::from("currency")])]
+        );
+        assert_eq!(items[0].to_string(), "Gold Coin x4");
+    }
+
+    #[test]
+    fn item_take_splits_stacks() {
+        let mut items = vec![Item::new("Gold Coin").with_quantity(4).with_desc("A coin.")];
+
+        let taken = take(&mut items, "Gold Coin", 3).unwrap();
+        assert_eq!(
+            taken,
+            Item::new("Gold Coin").with_quantity(3).with_desc("A coin.")
+        );
+        assert_eq!(items[0].get_quantity(), 1);
+
+        let taken = take(&mut items, "gold coin", 5).unwrap();
+        assert_eq!(taken.get_quantity(), 1);
+        assert!(items.is_empty());
+        assert!(take(&mut items, "Gold Coin", 1).is_none());
+    }
+
+    #[test]
+    fn item_reads_saved_names() {
+        let items: Vec<Item> =
+            serde_yaml::from_str("[Flashlight, {name: Gold Coin, quantity: 2}]").unwrap();
+
+        assert_eq!(
+            items,
+            vec![
+                Item::new("Flashlight"),
+                Item::new("Gold Coin").with_quantity(2)
+            ]
+        );
+        assert_eq!(
+            serde_yaml::to_string(&items).unwrap(),
+            "- name: Flashlight\n- name: Gold Coin\n  quantity: 2\n"
+        );
+    }
+
+    #[test]
+    fn item_stacks_saturate() {
+        let mut items = vec![Item::new("Gold Coin").with_quantity(u32::MAX - 1)];
+        add(&mut items, Item::new("Gold Coin").with_quantity(5));
+
+        assert_eq!(items[0].get_quantity(), u32::MAX);
+    }
+}
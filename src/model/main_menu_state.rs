@@ -2,6 +2,14 @@
 pub struct MainMenuState {
     options: Vec<String>,
     selection: usize,
+    menu: Menu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Menu {
+    Main,
+    /// Picking the prompt pack to start a new game with.
+    Packs,
 }
 
 impl MainMenuState {
@@ -14,6 +22,15 @@ impl MainMenuState {
                 String::from("Quit"),
             ],
             selection: 0,
+            menu: Menu::Main,
+        }
+    }
+
+    pub fn packs(names: Vec<String>) -> Self {
+        MainMenuState {
+            options: names,
+            selection: 0,
+            menu: Menu::Packs,
         }
     }
 
@@ -39,4 +56,8 @@ impl MainMenuState {
     pub fn get_selection_index(&self) -> usize {
         self.selection
     }
+
+    pub fn get_menu(&self) -> Menu {
+        self.menu
+    }
 }
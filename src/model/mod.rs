@@ -1,12 +1,15 @@
 use self::{game_state::GameState, main_menu_state::MainMenuState};
 
 pub mod game_state;
+pub mod item;
 pub mod main_menu_state;
+pub mod stats;
+pub mod world;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Model {
     MainMenu(MainMenuState),
-    Game(GameState),
+    Game(Box<GameState>),
 }
 
 impl Model {
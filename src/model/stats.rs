@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+/// The most health and stamina the player can have.
+pub const MAX_HEALTH: u32 = 100;
+pub const MAX_STAMINA: u32 = 100;
+
+/// A stat the model can change. Models are not always consistent about case, so capitalized
+/// names are accepted too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stat {
+    #[serde(alias = "Health")]
+    Health,
+    #[serde(alias = "Stamina")]
+    Stamina,
+    #[serde(alias = "Gold")]
+    Gold,
+}
+
+impl Stat {
+    /// The names of the stats, as they are written in actions.
+    pub const NAMES: &'static [&'static str] = &["health", "stamina", "gold"];
+}
+
+/// The player's health, stamina and gold. Health and stamina are kept between zero and their
+/// maximum, and gold is never negative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    health: u32,
+    stamina: u32,
+    gold: u32,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            health: MAX_HEALTH,
+            stamina: MAX_STAMINA,
+            gold: 0,
+        }
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats::default()
+    }
+
+    /// Changes a stat by the given amount, clamping it to its range.
+    pub fn modify(&mut self, stat: Stat, change: i32) {
+        let (value, max) = match stat {
+            Stat::Health => (&mut self.health, MAX_HEALTH),
+            Stat::Stamina => (&mut self.stamina, MAX_STAMINA),
+            Stat::Gold => (&mut self.gold, u32::MAX),
+        };
+        *value = value.saturating_add_signed(change).min(max);
+    }
+
+    /// Whether the player has run out of health.
+    pub fn is_dead(&self) -> bool {
+        self.health == 0
+    }
+
+    pub fn get_health(&self) -> u32 {
+        self.health
+    }
+
+    pub fn get_stamina(&self) -> u32 {
+        self.stamina
+    }
+
+    pub fn get_gold(&self) -> u32 {
+        self.gold
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stats_stay_in_range() {
+        let mut stats = Stats::new();
+        stats.modify(Stat::Health, 20);
+        stats.modify(Stat::Stamina, -30);
+        stats.modify(Stat::Gold, -5);
+        assert_eq!(
+            (stats.get_health(), stats.get_stamina(), stats.get_gold()),
+            (MAX_HEALTH, MAX_STAMINA - 30, 0)
+        );
+
+        stats.modify(Stat::Gold, 12);
+        stats.modify(Stat::Health, -150);
+        assert_eq!(stats.get_gold(), 12);
+        assert!(stats.is_dead());
+    }
+
+    #[test]
+    fn stats_read_stat_names_in_any_case() {
+        let stats: Vec<Stat> = serde_yaml::from_str("[health, Stamina, Gold]").unwrap();
+        assert_eq!(stats, vec![Stat::Health, Stat::Stamina, Stat::Gold]);
+        assert!(serde_yaml::from_str::<Stat>("mana").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::item::{self, Item};
+
+/// The scenes the player has visited and how they connect, so a scene the player returns to is
+/// the same one they left. Scenes are keyed by an ID derived from their name.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct World {
+    scenes: BTreeMap<String, Scene>,
+    /// The ID of the scene the player is in.
+    current: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    id: String,
+    name: String,
+    desc: String,
+    /// The IDs of the scenes the player has moved to or from this one, in the order they were
+    /// first used.
+    exits: Vec<String>,
+    /// The messages of everything that happened here, oldest first.
+    events: Vec<String>,
+    /// The items lying around, which the player can pick up.
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+impl Scene {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_desc(&self) -> &str {
+        &self.desc
+    }
+
+    pub fn get_exits(&self) -> &[String] {
+        &self.exits
+    }
+
+    pub fn get_events(&self) -> &[String] {
+        &self.events
+    }
+
+    pub fn get_items(&self) -> &[Item] {
+        &self.items
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        World::default()
+    }
+
+    /// The ID of a scene with the given name: its words in lowercase, joined by dashes. Since an
+    /// ID maps to itself, a scene can be entered by its name or its ID.
+    pub fn scene_id(name: &str) -> String {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+
+    /// Moves the player to the scene with the given name, linking it to the scene they came from.
+    /// A scene seen before keeps its stored name and description, and a new one is added with
+    /// the ones given.
+    pub fn enter(&mut self, name: &str, desc: &str) -> &Scene {
+        let id = World::scene_id(name);
+        self.scenes.entry(id.clone()).or_insert_with(|| Scene {
+            id: id.clone(),
+            name: String::from(name),
+            desc: String::from(desc),
+            exits: Vec::new(),
+            events: Vec::new(),
+            items: Vec::new(),
+        });
+        if let Some(from) = self.current.replace(id.clone()) {
+            if from != id {
+                self.link(&from, &id);
+                self.link(&id, &from);
+            }
+        }
+
+        &self.scenes[&id]
+    }
+
+    /// Records something that happened in the current scene. Nothing is recorded before the
+    /// player has entered a scene.
+    pub fn record_event(&mut self, message: &str) {
+        if let Some(scene) = self.current_scene_mut() {
+            scene.events.push(String::from(message));
+        }
+    }
+
+    /// Leaves an item in the current scene.
+    pub fn add_item(&mut self, item: Item) {
+        if let Some(scene) = self.current_scene_mut() {
+            item::add(&mut scene.items, item);
+        }
+    }
+
+    /// Takes up to `quantity` of an item from the current scene, if it is there.
+    pub fn take_item(&mut self, name: &str, quantity: u32) -> Option<Item> {
+        item::take(&mut self.current_scene_mut()?.items, name, quantity)
+    }
+
+    pub fn get_current(&self) -> Option<&Scene> {
+        self.scenes.get(self.current.as_ref()?)
+    }
+
+    pub fn get_scenes(&self) -> impl Iterator<Item = &Scene> {
+        self.scenes.values()
+    }
+
+    fn current_scene_mut(&mut self) -> Option<&mut Scene> {
+        self.scenes.get_mut(self.current.as_ref()?)
+    }
+
+    fn link(&mut self, from: &str, to: &str) {
+        if let Some(scene) = self.scenes.get_mut(from) {
+            if !scene.exits.iter().any(|exit| exit == to) {
+                scene.exits.push(String::from(to));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn world_scene_ids_ignore_case_and_punctuation() {
+        assert_eq!(World::scene_id("The Old House"), "the-old-house");
+        assert_eq!(World::scene_id("  the old-house! "), "the-old-house");
+        assert_eq!(World::scene_id("the-old-house"), "the-old-house");
+    }
+
+    #[test]
+    fn world_restores_known_scenes() {
+        let mut world = World::new();
+        world.enter("The Old House", "An abandoned house.");
+        world.record_event("You take the lamp.");
+        world.enter("The Overgrown Yard", "Weeds everywhere.");
+
+        let house = world.enter("the old house", "A freshly painted house.");
+
+        assert_eq!(house.get_name(), "The Old House");
+        assert_eq!(house.get_desc(), "An abandoned house.");
+        assert_eq!(house.get_events(), &[String::from("You take the lamp.")]);
+        assert_eq!(house.get_exits(), &[String::from("the-overgrown-yard")]);
+        assert_eq!(world.get_scenes().count(), 2);
+    }
+
+    #[test]
+    fn world_keeps_items_in_their_scene() {
+        let mut world = World::new();
+        world.enter("House", "");
+        world.add_item(Item::new("Brass Lamp"));
+        world.add_item(Item::new("Rope"));
+        world.enter("Yard", "");
+        assert!(world.take_item("rope", 1).is_none());
+
+        let house = world.enter("House", "");
+        assert_eq!(
+            house.get_items(),
+            &[Item::new("Brass Lamp"), Item::new("Rope")]
+        );
+
+        assert!(world.take_item("brass lamp", 1).is_some());
+        assert!(world.take_item("Candle", 1).is_none());
+        assert_eq!(
+            world.get_current().unwrap().get_items(),
+            &[Item::new("Rope")]
+        );
+    }
+
+    #[test]
+    fn world_links_scenes_both_ways_once() {
+        let mut world = World::new();
+        world.enter("House", "");
+        world.enter("Yard", "");
+        world.enter("House", "");
+        world.enter("Yard", "");
+        world.enter("Yard", "");
+        world.enter("Forest", "");
+
+        let exits: Vec<(&str, &[String])> = world
+            .get_scenes()
+            .map(|scene| (scene.get_id(), scene.get_exits()))
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                ("forest", &[String::from("yard")][..]),
+                ("house", &[String::from("yard")][..]),
+                ("yard", &[String::from("house"), String::from("forest")][..]),
+            ]
+        );
+        assert_eq!(world.get_current().unwrap().get_id(), "forest");
+    }
+}
@@ -9,15 +9,21 @@ use ratatui::{
     prelude::{CrosstermBackend, Terminal},
     style::Color,
     text::Text,
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Wrap},
 };
 
 use crossterm::{
-    cursor::{EnableBlinking, MoveTo, SetCursorStyle, Show},
+    cursor::{EnableBlinking, Hide, MoveTo, SetCursorStyle, Show},
     execute,
 };
 
-use crate::model::game_state::GameState;
+use crate::model::{
+    game_state::GameState,
+    item::Item,
+    stats::{MAX_HEALTH, MAX_STAMINA},
+};
+
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
 
 pub struct GameRenderer {
     terminal: Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>>,
@@ -36,7 +42,10 @@ impl GameRenderer {
         let margin = 1;
         terminal
             .draw(|frame| {
-                let size = frame.size();
+                let outer_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                    .split(frame.size());
                 let inventory_width = get_inventory_width(state.get_inventory());
                 let horizontal_chunks = Layout::default()
                     .direction(Direction::Horizontal)
@@ -44,7 +53,7 @@ impl GameRenderer {
                     .constraints(
                         [Constraint::Min(10), Constraint::Length(inventory_width)].as_ref(),
                     )
-                    .split(size);
+                    .split(outer_chunks[0]);
 
                 let description = Paragraph::new(state.get_scene_desc())
                     .style(ratatui::style::Style::default().fg(Color::Yellow))
@@ -69,10 +78,11 @@ impl GameRenderer {
                     .split(horizontal_chunks[0]);
                 frame.render_widget(description, vertical_chunks[0]);
 
-                let history_items = paragraph_list_to_lines(
-                    state.get_scene_history(),
-                    vertical_chunks[1].width - 2,
-                );
+                let mut history = state.get_scene_history().clone();
+                if let Some(pending_response) = state.get_pending_response() {
+                    history.push(pending_response.to_string());
+                }
+                let history_items = paragraph_list_to_lines(&history, vertical_chunks[1].width - 2);
 
                 let max_history_items =
                     min(vertical_chunks[1].height - 2, history_items.len() as u16) as usize;
@@ -95,29 +105,68 @@ impl GameRenderer {
                     List::new(history_items).block(Block::default().borders(Borders::ALL)),
                     vertical_chunks[1],
                 );
-                frame.render_widget(
+                let input = if state.is_thinking() {
+                    let spinner = SPINNER_FRAMES[state.get_spinner_frame() % SPINNER_FRAMES.len()];
+                    let status = state.get_thinking_status().unwrap_or("Thinking...");
+                    Paragraph::new(format!("{} {} (Esc to cancel)", spinner, status))
+                        .style(ratatui::style::Style::default().fg(Color::DarkGray))
+                } else {
                     Paragraph::new(state.get_user_entry())
                         .style(ratatui::style::Style::default().fg(Color::White))
-                        .block(Block::default().borders(Borders::ALL).title("Input")),
+                };
+                let input_title = match state.is_entry_enabled() || state.is_thinking() {
+                    true => "Input",
+                    false => "Game Over (Esc to quit)",
+                };
+                frame.render_widget(
+                    input.block(Block::default().borders(Borders::ALL).title(input_title)),
                     vertical_chunks[2],
                 );
 
+                let side_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(
+                        [
+                            Constraint::Length(3),
+                            Constraint::Length(3),
+                            Constraint::Length(3),
+                            Constraint::Min(3),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(horizontal_chunks[1]);
+                let stats = state.get_stats();
+                frame.render_widget(
+                    stat_gauge("Health", stats.get_health(), MAX_HEALTH, Color::Red),
+                    side_chunks[0],
+                );
+                frame.render_widget(
+                    stat_gauge("Stamina", stats.get_stamina(), MAX_STAMINA, Color::Green),
+                    side_chunks[1],
+                );
+                frame.render_widget(
+                    Paragraph::new(stats.get_gold().to_string())
+                        .style(ratatui::style::Style::default().fg(Color::Yellow))
+                        .block(Block::default().borders(Borders::ALL).title("Gold")),
+                    side_chunks[2],
+                );
+
                 let inventory_items: Vec<ListItem> = state
                     .get_inventory()
                     .iter()
-                    .map(|item| {
-                        if item.len() > 20 {
-                            return format!("{}..", &item[..18]);
-                        }
-                        item.to_string()
-                    })
-                    .map(|item| ListItem::new(Text::raw(item)))
+                    .map(|item| ListItem::new(Text::raw(inventory_label(item))))
                     .collect();
 
                 frame.render_widget(
                     List::new(inventory_items)
                         .block(Block::default().borders(Borders::ALL).title("Inventory")),
-                    horizontal_chunks[1],
+                    side_chunks[3],
+                );
+
+                frame.render_widget(
+                    Paragraph::new(state.get_usage_status())
+                        .style(ratatui::style::Style::default().fg(Color::DarkGray)),
+                    outer_chunks[1],
                 );
             })
             .expect("Failed to draw game frame.");
@@ -126,9 +175,14 @@ impl GameRenderer {
             .size()
             .expect("Unable to get terminal size.")
             .height
-            - 2
+            - 3
             - margin;
 
+        if state.is_thinking() {
+            execute!(io::stdout(), Hide).expect("Unable to hide cursor.");
+            return;
+        }
+
         // Moves the position and display style of the terminal cursor to match the input.
         execute!(
             io::stdout(),
@@ -164,11 +218,41 @@ fn paragraph_list_to_lines(paragraphs: &Vec<String>, width: u16) -> Vec<String>
     lines
 }
 
-fn get_inventory_width(inventory: &[String]) -> u16 {
+/// A bordered gauge showing how much of a stat the player has left.
+fn stat_gauge(title: &str, value: u32, max: u32, color: Color) -> Gauge<'_> {
+    Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .gauge_style(ratatui::style::Style::default().fg(color))
+        .ratio(f64::from(value.min(max)) / f64::from(max))
+        .label(format!("{}/{}", value, max))
+}
+
+/// The item's name, cut down to fit the inventory pane, followed by its count when there is more
+/// than one.
+fn inventory_label(item: &Item) -> String {
+    let count = match item.get_quantity() {
+        1 => String::new(),
+        quantity => format!(" x{}", quantity),
+    };
+    let max_name = 20 - count.len().min(10);
+    let name = item.get_name();
+    if name.chars().count() > max_name {
+        let shortened: String = name.chars().take(max_name - 2).collect();
+        return format!("{}..{}", shortened, count);
+    }
+    format!("{}{}", name, count)
+}
+
+fn get_inventory_width(inventory: &[Item]) -> u16 {
     std::cmp::min(
         std::cmp::max(
             12,
-            inventory.iter().map(|item| item.len()).max().unwrap_or(0) + 2,
+            inventory
+                .iter()
+                .map(|item| inventory_label(item).chars().count())
+                .max()
+                .unwrap_or(0)
+                + 2,
         ) as u16,
         20,
     )
@@ -10,7 +10,7 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem},
 };
 
-use crate::model::main_menu_state::MainMenuState;
+use crate::model::main_menu_state::{MainMenuState, Menu};
 
 pub struct MainMenuRenderer {
     terminal: Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>>,
@@ -44,7 +44,11 @@ impl MainMenuRenderer {
                     })
                     .collect();
 
-                let list = List::new(items).block(Block::default().borders(Borders::ALL)); // Optional border
+                let mut block = Block::default().borders(Borders::ALL); // Optional border
+                if state.get_menu() == Menu::Packs {
+                    block = block.title("Choose a story");
+                }
+                let list = List::new(items).block(block);
 
                 frame.render_widget(list, frame.size());
             })
@@ -30,12 +30,10 @@ impl UI {
 
         let join_handle = std::thread::spawn(move || loop {
             match model_update_rx.recv() {
-                Ok(Message::StateUpdate(Model::MainMenu(state))) => {
-                    main_menu_renderer.render(state);
-                }
-                Ok(Message::StateUpdate(Model::Game(state))) => {
-                    game_renderer.render(state);
-                }
+                Ok(Message::StateUpdate(model)) => match *model {
+                    Model::MainMenu(state) => main_menu_renderer.render(state),
+                    Model::Game(state) => game_renderer.render(*state),
+                },
                 Ok(Message::Terminate) => {
                     break;
                 }
@@ -0,0 +1,108 @@
+// The worker owns the game loader and runs LLM calls off the dispatcher thread, so that the UI can
+// keep redrawing and responding to keys while a turn is pending
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use crate::{
+    game_loader::GameLoader,
+    message::{TurnEvent, TurnRequest},
+};
+
+/// The worker thread is not joined when the worker is dropped, so quitting the game does not have
+/// to wait for a pending request to finish.
+pub struct Worker {
+    request_tx: Sender<TurnRequest>,
+    event_rx: Receiver<TurnEvent>,
+}
+
+impl Worker {
+    pub fn new(mut loader: GameLoader) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            // Exits once the worker is dropped and the request channel hangs up.
+            while let Ok(request) = request_rx.recv() {
+                let (id, cancellation, actions) = match request {
+                    TurnRequest::StartGame {
+                        id,
+                        pack,
+                        cancellation,
+                    } => {
+                        let actions = loader.start_game(&pack, &cancellation, &mut |progress| {
+                            let _ = event_tx.send(TurnEvent::Progress { id, progress });
+                        });
+                        (id, cancellation, actions)
+                    }
+                    TurnRequest::Input {
+                        id,
+                        input,
+                        state,
+                        cancellation,
+                    } => {
+                        let actions =
+                            loader.process_input(&input, &state, &cancellation, &mut |progress| {
+                                // The dispatcher may have gone away, in which case nobody needs
+                                // the progress.
+                                let _ = event_tx.send(TurnEvent::Progress { id, progress });
+                            });
+                        (id, cancellation, actions)
+                    }
+                    TurnRequest::Resume { id, cancellation } => {
+                        // Nobody is waiting for a cancelled resume.
+                        if cancellation.is_cancelled() {
+                            continue;
+                        }
+                        let state = loader.resume().map(Box::new);
+                        if event_tx.send(TurnEvent::Resumed { id, state }).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    TurnRequest::Commit { state } => {
+                        loader.commit();
+                        loader.save(&state);
+                        continue;
+                    }
+                };
+
+                // Nobody is waiting for a cancelled turn.
+                if cancellation.is_cancelled() {
+                    continue;
+                }
+                let usage = loader.get_usage();
+                if event_tx
+                    .send(TurnEvent::Done { id, actions, usage })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Worker {
+            request_tx,
+            event_rx,
+        }
+    }
+
+    pub fn send(&self, request: TurnRequest) {
+        self.request_tx
+            .send(request)
+            .expect("Failed to send request to worker.");
+    }
+
+    /// Returns the next event from the worker, if there is one, without blocking.
+    pub fn try_recv(&self) -> Option<TurnEvent> {
+        match self.event_rx.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Worker thread has stopped."),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn recv(&self) -> TurnEvent {
+        self.event_rx.recv().expect("Worker thread has stopped.")
+    }
+}